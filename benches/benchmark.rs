@@ -1,7 +1,7 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use avl::AvlTreeMap;
+use avl::{AvlTreeMap, AvlTreeSet};
 
 const N: usize = 100_000;
 
@@ -18,6 +18,44 @@ pub fn benchmarks(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("map_extend", |b| {
+        b.iter(|| {
+            let mut map: AvlTreeMap<_, _> = (0..10).map(|value| (value, value)).collect();
+            map.extend(values.iter().map(|value| (*value, *value)));
+            black_box(map);
+        })
+    });
+
+    c.bench_function("map_extend_sorted_greater", |b| {
+        b.iter(|| {
+            let mut map = AvlTreeMap::new();
+            for chunk in (0..N as i32).collect::<Vec<_>>().chunks(100) {
+                map.extend_sorted_greater(chunk.iter().map(|key| (*key, *key)));
+            }
+            black_box(map);
+        })
+    });
+
+    c.bench_function("map_extend_time_series", |b| {
+        b.iter(|| {
+            let mut map = AvlTreeMap::new();
+            for chunk in (0..N as i32).collect::<Vec<_>>().chunks(100) {
+                map.extend(chunk.iter().map(|key| (*key, *key)));
+            }
+            black_box(map);
+        })
+    });
+
+    c.bench_function("map_extend_naive", |b| {
+        b.iter(|| {
+            let mut map: AvlTreeMap<_, _> = (0..10).map(|value| (value, value)).collect();
+            for value in &values {
+                map.insert(*value, *value);
+            }
+            black_box(map);
+        })
+    });
+
     let mut map = AvlTreeMap::new();
     for value in &values {
         map.insert(*value, *value);
@@ -39,6 +77,70 @@ pub fn benchmarks(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("map_iter_fold", |b| {
+        b.iter(|| black_box(map.iter().fold(0i64, |acc, (_, v)| acc + i64::from(*v))))
+    });
+
+    c.bench_function("map_fold_in_order", |b| {
+        b.iter(|| black_box(map.fold_in_order(0i64, |acc, _, v| acc + i64::from(*v))))
+    });
+
+    // Churn a map so its nodes get scattered across the heap
+    let mut churned = AvlTreeMap::new();
+    for value in &values {
+        churned.insert(*value, *value);
+        churned.insert(value.wrapping_mul(7), *value);
+        churned.remove(&value.wrapping_mul(3));
+    }
+    churned.shrink_to_fit();
+
+    c.bench_function("map_iter_churned", |b| {
+        b.iter(|| black_box(churned.iter().fold(0i64, |acc, (_, v)| acc + i64::from(*v))))
+    });
+
+    churned.rebuild();
+
+    c.bench_function("map_iter_rebuilt", |b| {
+        b.iter(|| black_box(churned.iter().fold(0i64, |acc, (_, v)| acc + i64::from(*v))))
+    });
+
+    c.bench_function("map_clone", |b| {
+        let mut target = map.clone();
+        b.iter(|| {
+            target = map.clone();
+        });
+        black_box(target);
+    });
+
+    c.bench_function("map_clone_from", |b| {
+        let mut target = map.clone();
+        b.iter(|| {
+            target.clone_from(&map);
+        });
+        black_box(target);
+    });
+
+    c.bench_function("map_insert_remove", |b| {
+        let mut map = map.clone();
+        b.iter(|| {
+            for value in &values {
+                map.remove(value);
+                map.insert(*value, *value);
+            }
+        })
+    });
+
+    c.bench_function("map_insert_remove_with_capacity", |b| {
+        let mut reserved = AvlTreeMap::with_capacity(map.len());
+        reserved.clone_from(&map);
+        b.iter(|| {
+            for value in &values {
+                reserved.remove(value);
+                reserved.insert(*value, *value);
+            }
+        })
+    });
+
     c.bench_function("map_remove", |b| {
         let mut map = map.clone();
         b.iter(|| {
@@ -47,6 +149,97 @@ pub fn benchmarks(c: &mut Criterion) {
             }
         })
     });
+
+    let mut sorted_values = values.clone();
+    sorted_values.sort_unstable();
+    sorted_values.dedup();
+    let evicted: Vec<_> = sorted_values.iter().cloned().step_by(2).collect();
+
+    c.bench_function("map_remove_all", |b| {
+        b.iter_batched(
+            || map.clone(),
+            |mut map| {
+                black_box(map.remove_all(&evicted));
+                map
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("map_remove_loop", |b| {
+        b.iter_batched(
+            || map.clone(),
+            |mut map| {
+                for key in &evicted {
+                    black_box(map.remove(key));
+                }
+                map
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("set_from_range", |b| {
+        b.iter(|| black_box(AvlTreeSet::from_range(0..N as i32)))
+    });
+
+    c.bench_function("set_collect_range", |b| {
+        b.iter(|| black_box((0..N as i32).collect::<AvlTreeSet<_>>()))
+    });
+
+    let superset: AvlTreeSet<_> = values.iter().cloned().collect();
+    let subset: AvlTreeSet<_> = values.iter().cloned().step_by(3).collect();
+
+    c.bench_function("set_is_subset", |b| {
+        b.iter(|| {
+            black_box(subset.is_subset(&superset));
+        })
+    });
+
+    let sorted_batch: Vec<_> = superset.iter().cloned().step_by(2).collect();
+
+    c.bench_function("set_contains_all", |b| {
+        b.iter(|| {
+            black_box(superset.contains_all(&sorted_batch));
+        })
+    });
+
+    c.bench_function("set_contains_loop", |b| {
+        b.iter(|| {
+            black_box(sorted_batch.iter().all(|value| superset.contains(value)));
+        })
+    });
+
+    let other: AvlTreeSet<_> = values.iter().cloned().skip(N / 2).step_by(2).collect();
+
+    c.bench_function("set_bitand", |b| {
+        b.iter(|| {
+            black_box(&superset & &other);
+        })
+    });
+
+    c.bench_function("set_intersection_collect", |b| {
+        b.iter(|| {
+            black_box(
+                superset
+                    .intersection(&other)
+                    .cloned()
+                    .collect::<AvlTreeSet<_>>(),
+            );
+        })
+    });
+
+    c.bench_function("set_bitor", |b| {
+        b.iter(|| {
+            black_box(&superset | &other);
+        })
+    });
+
+    c.bench_function("set_union_collect", |b| {
+        b.iter(|| {
+            black_box(superset.union(&other).cloned().collect::<AvlTreeSet<_>>());
+        })
+    });
 }
 
 criterion_group!(benches, benchmarks);
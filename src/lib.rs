@@ -9,6 +9,30 @@ pub use map::AvlTreeMap;
 #[doc(inline)]
 pub use set::AvlTreeSet;
 
+/// Implements `last`, `min` and `max` of an iterator in ascending key order
+/// by taking a single item from the respective end instead of consuming the iterator.
+macro_rules! sorted_iterator_methods {
+    () => {
+        fn last(mut self) -> Option<Self::Item> {
+            self.next_back()
+        }
+
+        fn min(mut self) -> Option<Self::Item>
+        where
+            Self::Item: Ord,
+        {
+            self.next()
+        }
+
+        fn max(mut self) -> Option<Self::Item>
+        where
+            Self::Item: Ord,
+        {
+            self.next_back()
+        }
+    };
+}
+
 pub mod map;
 pub mod set;
 
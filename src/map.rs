@@ -6,11 +6,24 @@ use core::borrow::Borrow;
 use core::cmp::{self, Ordering};
 use core::fmt;
 use core::hash::{Hash, Hasher};
-use core::iter::FromIterator;
+use core::iter::{FromIterator, Rev};
 use core::marker::PhantomData;
 use core::mem;
+use core::mem::MaybeUninit;
 use core::ops::{Bound, Index, RangeBounds};
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{
+    plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer},
+    FromParallelIterator, IntoParallelIterator, ParallelExtend, ParallelIterator,
+};
+#[cfg(feature = "rayon")]
+use rayon::slice::ParallelSliceMut;
+
+mod sum_map;
+
+pub use sum_map::{AvlTreeSumMap, SumMapIter};
 
 /// An ordered map implemented with an AVL tree.
 ///
@@ -24,13 +37,59 @@ use core::ptr::NonNull;
 /// map.remove(&1);
 /// assert!(map.get(&1).is_none());
 /// ```
+///
+/// Only operations that look up keys, such as `get`, `insert`, `remove` or `range`,
+/// require `K: Ord`. Creating, clearing and iterating a map works for any key type.
 pub struct AvlTreeMap<K, V> {
     root: Link<K, V>,
     num_nodes: usize,
+    // Cached first and last node, kept up to date on insert and remove
+    min: Link<K, V>,
+    max: Link<K, V>,
+    // Removed nodes kept for reuse, singly linked by their parent link.
+    // Their keys and values are uninitialized.
+    free: Link<K, V>,
+    num_free: usize,
+    // Capacity requested by with_capacity, up to which removed nodes are kept for reuse
+    reserved: usize,
+    #[cfg(feature = "rotation_observer")]
+    rotation_observer: Option<RotationObserver<K>>,
+}
+
+#[cfg(feature = "rotation_observer")]
+type RotationObserver<K> = Box<dyn FnMut(RotationEvent<'_, K>) + Send>;
+
+/// The direction of a rotation, reported by a [`RotationEvent`].
+///
+/// [`RotationEvent`]: struct.RotationEvent.html
+#[cfg(feature = "rotation_observer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationDirection {
+    Left,
+    Right,
+}
+
+/// A rotation of the tree, reported to the observer set by [`set_rotation_observer`].
+/// Requires the `rotation_observer` feature.
+///
+/// [`set_rotation_observer`]: struct.AvlTreeMap.html#method.set_rotation_observer
+#[cfg(feature = "rotation_observer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationEvent<'a, K> {
+    /// Key of the node that is rotated down.
+    pub key: &'a K,
+    /// Direction of the rotation.
+    pub direction: RotationDirection,
 }
 
 /// A node in the binary search tree, containing links to its parent node, left child, right child,
 /// its height (== maximum number of links to follow to reach a leaf node) and a key, a value.
+///
+/// Links are `Option<NonNull>`, which has the size of a plain pointer. The compiler reorders
+/// the fields, so the small height shares the padding bytes after key and value, if any.
+/// On 64 bit platforms a `Node<u64, u64>` takes 48 bytes: 24 bytes for the links, 16 bytes for
+/// key and value and 8 bytes for the height and its padding.
+/// Storing the child links in an array indexed by direction would not reduce this size.
 struct Node<K, V> {
     parent: Link<K, V>,
     left: Link<K, V>,
@@ -44,6 +103,94 @@ type NodePtr<K, V> = NonNull<Node<K, V>>;
 type Link<K, V> = Option<NodePtr<K, V>>;
 type LinkPtr<K, V> = NonNull<Link<K, V>>;
 
+/// Diagnostics about the work done to keep the tree balanced during an insert.
+///
+/// This `struct` is returned by the [`insert_with_stats`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`insert_with_stats`]: struct.AvlTreeMap.html#method.insert_with_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InsertStats {
+    /// Number of nodes whose height grew by the insert.
+    pub heights_changed: usize,
+    /// Whether a single or double rotation has been necessary to restore balance.
+    pub rotated: bool,
+}
+
+/// The outcome of an insert, telling whether the tree structure has changed.
+///
+/// This `enum` is returned by the [`insert_checked`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`insert_checked`]: struct.AvlTreeMap.html#method.insert_checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertResult<V> {
+    /// The key was already present, its value has been replaced by the new value.
+    /// Holds the old value; the tree structure has not changed.
+    Updated(V),
+    /// The key was not present and a new entry has been inserted.
+    Inserted {
+        /// Whether a rotation has been necessary to restore balance.
+        rotated: bool,
+    },
+}
+
+/// The error returned by [`try_range`] for an invalid range.
+///
+/// [`try_range`]: struct.AvlTreeMap.html#method.try_range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError {
+    /// Range start is greater than range end.
+    StartGreaterThanEnd,
+    /// Range start and end are equal and both excluded.
+    EmptyExcludedExcluded,
+}
+
+/// Statistics about the shape of the tree of a map.
+///
+/// This `struct` is returned by the [`stats`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`stats`]: struct.AvlTreeMap.html#method.stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of elements in the map.
+    pub len: usize,
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub height: usize,
+    /// Number of nodes on the shortest path from the root to a leaf.
+    pub min_depth: usize,
+    /// Whether the height is within the AVL bound of about `1.44 * log2(len + 2)`,
+    /// i.e. no AVL tree of this height has fewer elements.
+    pub within_avl_bound: bool,
+}
+
+/// The side(s) of a merge join a key has been found on, together with its value(s).
+///
+/// This `enum` is yielded by the [`merge_join`] iterator on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`merge_join`]: struct.AvlTreeMap.html#method.merge_join
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeSide<'a, V> {
+    /// The key is only in `self`.
+    Left(&'a V),
+    /// The key is only in `other`.
+    Right(&'a V),
+    /// The key is in both maps, with the values of `self` and `other`.
+    Both(&'a V, &'a V),
+}
+
+/// A wrapper to show the tree structure of a map with `{:?}`.
+///
+/// This `struct` is created by the [`debug_tree`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`debug_tree`]: struct.AvlTreeMap.html#method.debug_tree
+pub struct DebugTree<'a, K, V> {
+    map: &'a AvlTreeMap<K, V>,
+}
+
 /// A view into a single map entry, which may either be vacant or occupied.
 pub enum Entry<'a, K: 'a, V: 'a> {
     Vacant(VacantEntry<'a, K, V>),
@@ -66,6 +213,28 @@ pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
     marker: PhantomData<(&'a K, &'a mut V)>,
 }
 
+/// A builder for raw entries of a map, created by [`raw_entry_mut`].
+///
+/// [`raw_entry_mut`]: struct.AvlTreeMap.html#method.raw_entry_mut
+pub struct RawEntryBuilderMut<'a, K: 'a, V: 'a> {
+    map: &'a mut AvlTreeMap<K, V>,
+}
+
+/// A view into a single map entry looked up by a borrowed key,
+/// which may either be vacant or occupied.
+pub enum RawEntryMut<'a, K: 'a, V: 'a> {
+    Vacant(RawVacantEntryMut<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+/// A view into a vacant map entry without an owned key. It is part of the RawEntryMut enum.
+pub struct RawVacantEntryMut<'a, K: 'a, V: 'a> {
+    map: &'a mut AvlTreeMap<K, V>,
+    parent: Link<K, V>,
+    insert_pos: LinkPtr<K, V>,
+    marker: PhantomData<(&'a K, &'a mut V)>,
+}
+
 /// An insert position in the map for given key.
 enum InsertPos<K, V> {
     Vacant {
@@ -82,6 +251,35 @@ pub struct Iter<'a, K, V> {
     node_iter: NodeIter<'a, K, V>,
 }
 
+/// An iterator over the entries of a map together with their zero-based position.
+pub struct IterIndexed<'a, K, V> {
+    iter: Iter<'a, K, V>,
+    front: usize,
+    back: usize,
+}
+
+/// An iterator merging the entries of two maps in key order.
+///
+/// This `struct` is created by the [`merge_join`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`merge_join`]: struct.AvlTreeMap.html#method.merge_join
+pub struct MergeJoin<'a, K, V> {
+    lhs_iter: Iter<'a, K, V>,
+    rhs_iter: Iter<'a, K, V>,
+}
+
+/// An iterator over groups of consecutive entries of a map with equal projected keys.
+///
+/// This `struct` is created by the [`group_by`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`group_by`]: struct.AvlTreeMap.html#method.group_by
+pub struct GroupBy<'a, K, V, F> {
+    iter: Iter<'a, K, V>,
+    proj: F,
+}
+
 /// An iterator over a range of entries of a map.
 pub struct Range<'a, K, V> {
     node_iter: NodeIter<'a, K, V>,
@@ -97,6 +295,11 @@ pub struct Values<'a, K, V> {
     node_iter: NodeIter<'a, K, V>,
 }
 
+/// An iterator over the entries of a map together with their depth in the tree.
+pub struct IterWithDepth<'a, K, V> {
+    node_iter: NodeIter<'a, K, V>,
+}
+
 /// A mutable iterator over the entries of a map.
 pub struct IterMut<'a, K, V> {
     node_iter: NodeIter<'a, K, V>,
@@ -112,36 +315,158 @@ pub struct ValuesMut<'a, K, V> {
     node_iter: NodeIter<'a, K, V>,
 }
 
+/// A parallel iterator over the entries of a map.
+///
+/// This `struct` is created by the `par_iter` method on [`AvlTreeMap`], which is provided by
+/// rayon's `IntoParallelRefIterator` trait. Requires the `rayon` feature.
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+#[cfg(feature = "rayon")]
+pub struct ParIter<'a, K, V> {
+    node_iter: NodeIter<'a, K, V>,
+}
+
+/// A producer for parallel iteration, which splits its range of nodes into sub ranges.
+#[cfg(feature = "rayon")]
+struct NodeProducer<'a, K, V> {
+    node_iter: NodeIter<'a, K, V>,
+}
+
 /// An owning iterator over the entries of a map.
 pub struct IntoIter<K, V> {
     node_eater: NodeEater<K, V>,
 }
 
+/// An iterator which removes and yields the entries of a map matching a predicate.
+///
+/// This `struct` is created by the [`extract_if`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`extract_if`]: struct.AvlTreeMap.html#method.extract_if
+pub struct ExtractIf<'a, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    inner: ExtractIfInner<'a, K, V>,
+    pred: F,
+}
+
+/// The predicate independent part of `ExtractIf`, shared with the set implementation.
+pub(crate) struct ExtractIfInner<'a, K, V> {
+    map: &'a mut AvlTreeMap<K, V>,
+    next: Link<K, V>,
+}
+
+/// A draining iterator over the entries of a map.
+///
+/// This `struct` is created by the [`drain`] method on [`AvlTreeMap`].
+///
+/// [`AvlTreeMap`]: struct.AvlTreeMap.html
+/// [`drain`]: struct.AvlTreeMap.html#method.drain
+pub struct Drain<'a, K, V> {
+    map: &'a mut AvlTreeMap<K, V>,
+    node_eater: NodeEater<K, V>,
+}
+
+/// An owning iterator over a sub-range of entries of a map.
+pub struct IntoRange<K, V> {
+    node_eater: NodeEater<K, V>,
+}
+
 /// Specifies a range [first, last] of tree nodes.
 /// Allows iteration by successively narrowing the range from either end.
 struct NodeIter<'a, K, V> {
     first: Link<K, V>,
     last: Link<K, V>,
+    /// Number of nodes in the range, if known.
+    len: Option<usize>,
     marker: PhantomData<&'a Node<K, V>>,
 }
 
+/// Owns the nodes of a detached tree and consumes them from both ends.
+/// Popped nodes are replaced by their sub tree, so the remaining nodes always form
+/// a valid tree which can be walked from first to last (e.g. by Debug implementations).
 struct NodeEater<K, V> {
     first: Link<K, V>,
     last: Link<K, V>,
+    len: usize,
 }
 
 // region Public implementation of AvlTreeMap
 impl<K, V> AvlTreeMap<K, V> {
     /// Creates an empty map.
     /// No memory is allocated until the first item is inserted.
-    pub fn new() -> Self
-    where
-        K: Ord,
-    {
+    pub const fn new() -> Self {
         Self {
             root: None,
             num_nodes: 0,
+            min: None,
+            max: None,
+            free: None,
+            num_free: 0,
+            reserved: 0,
+            #[cfg(feature = "rotation_observer")]
+            rotation_observer: None,
+        }
+    }
+
+    /// Sets an observer which is called on every rotation of the tree while rebalancing
+    /// after an insert or a removal, e.g. to visualize how the tree keeps its balance.
+    /// Requires the `rotation_observer` feature.
+    ///
+    /// A single rotation is reported as one event, a double rotation as two events.
+    /// Rotations while joining trees, i.e. in `append`, `extend_sorted_greater`, `split_off`
+    /// and `remove_range`, are not reported.
+    /// The observer is not cloned with the map.
+    #[cfg(feature = "rotation_observer")]
+    pub fn set_rotation_observer(&mut self, observer: RotationObserver<K>) {
+        self.rotation_observer = Some(observer);
+    }
+
+    /// Creates an empty map with memory preallocated for at least `capacity` elements.
+    ///
+    /// Nodes of removed elements are kept and reused for subsequent inserts as well,
+    /// as long as the capacity of the map does not exceed `capacity`. So a map with
+    /// a stable number of elements does not allocate after warming up.
+    /// Maps created otherwise deallocate the nodes of removed elements right away.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut map = Self::new();
+        map.reserved = capacity;
+        for _ in 0..capacity {
+            let boxed = Box::new(MaybeUninit::<Node<K, V>>::uninit());
+            let node_ptr = NonNull::from(Box::leak(boxed)).cast();
+            unsafe {
+                map.push_free_node(node_ptr);
+            }
+        }
+        map
+    }
+
+    /// Returns the number of elements the map can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        self.num_nodes + self.num_free
+    }
+
+    /// Deallocates the memory kept for reuse by removed elements,
+    /// keeping only the memory of the elements in the map.
+    pub fn shrink_to_fit(&mut self) {
+        while let Some(node_ptr) = self.free {
+            unsafe {
+                self.free = ptr::addr_of!((*node_ptr.as_ptr()).parent).read();
+                drop(Box::from_raw(
+                    node_ptr.cast::<MaybeUninit<Node<K, V>>>().as_ptr(),
+                ));
+            }
         }
+        self.num_free = 0;
+    }
+
+    /// Returns an estimate of the heap memory held by the map in bytes, in O(1) time.
+    ///
+    /// Counts the nodes of the elements and the nodes kept for reuse,
+    /// but not the heap memory owned by the keys and values themselves.
+    pub fn memory_usage(&self) -> usize {
+        self.capacity() * mem::size_of::<Node<K, V>>()
     }
 
     /// Returns true if the map contains no elements.
@@ -162,13 +487,31 @@ impl<K, V> AvlTreeMap<K, V> {
         }
     }
 
-    /// Clears the map, deallocating all memory.
+    #[cfg(test)]
+    pub fn node_size() -> usize {
+        mem::size_of::<Node<K, V>>()
+    }
+
+    /// Recomputes the height of every node from the heights of its children.
+    ///
+    /// Only the cached heights are repaired, the links between the nodes and thus the order
+    /// and balance of the tree are left unchanged. Takes O(n) time.
+    pub fn recompute_heights(&mut self) {
+        self.postorder(Self::adjust_height);
+    }
+
+    /// Clears the map, deallocating all memory
+    /// except the capacity requested by `with_capacity`, which is kept for reuse.
     pub fn clear(&mut self) {
-        self.postorder(|node_ptr| unsafe {
-            Node::destroy(node_ptr);
-        });
-        self.root = None;
-        self.num_nodes = 0;
+        drop(self.drain());
+    }
+
+    /// Takes the entries out of the map and returns them as a map, leaving `self` empty.
+    ///
+    /// No entries are moved or dropped; the memory kept for reuse by removed entries moves
+    /// along with them. Same as `mem::take(self)`, without needing the `Default` trait in scope.
+    pub fn take(&mut self) -> Self {
+        mem::take(self)
     }
 
     /// Returns a reference to the value corresponding to the key.
@@ -184,6 +527,55 @@ impl<K, V> AvlTreeMap<K, V> {
         Some(&unsafe { &*node_ptr.as_ptr() }.value)
     }
 
+    /// Returns the zero-based position of the key in the sorted order of the map,
+    /// or None if the key is not in the map.
+    ///
+    /// The tree does not track sub tree sizes, so this takes time linear in the position.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node_ptr = self.find(key)?;
+        let mut index = 0;
+        while let Some(prev_ptr) = Node::predecessor(node_ptr) {
+            node_ptr = prev_ptr;
+            index += 1;
+        }
+        Some(index)
+    }
+
+    /// Returns the number of entries in the sub tree rooted at the node of the key,
+    /// including the node itself, or None if the key is not in the map.
+    ///
+    /// The tree does not track sub tree sizes, so this takes time linear in the result.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn subtree_len<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node_ptr = self.find(key)?;
+        // The sub tree holds the range of nodes from its leftmost to its rightmost node
+        let mut first_ptr = node_ptr;
+        while let Some(left_ptr) = unsafe { first_ptr.as_ref().left } {
+            first_ptr = left_ptr;
+        }
+        let mut last_ptr = node_ptr;
+        while let Some(right_ptr) = unsafe { last_ptr.as_ref().right } {
+            last_ptr = right_ptr;
+        }
+        let keys = Keys {
+            node_iter: unsafe { NodeIter::new(Some(first_ptr), Some(last_ptr)) },
+        };
+        Some(keys.count())
+    }
+
     /// Returns a mutable reference to the value corresponding to the key.
     ///
     /// The key may be any borrowed form of the map's key type, but the ordering
@@ -197,6 +589,56 @@ impl<K, V> AvlTreeMap<K, V> {
         Some(&mut unsafe { &mut *node_ptr.as_ptr() }.value)
     }
 
+    /// Mutates the key equal to the given one in place, e.g. to update fields of the key
+    /// that do not take part in its ordering. Returns false if the key is not in the map.
+    ///
+    /// The closure must not change the ordering of the key relative to the other keys.
+    /// Doing so is a logic error and leaves the map in an unspecified (but memory safe) state.
+    /// Debug builds assert that the key is still ordered between its neighbors.
+    pub fn replace_key_in_place<Q, F>(&mut self, key: &Q, f: F) -> bool
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+        F: FnOnce(&mut K),
+    {
+        let node_ptr = match self.find(key) {
+            Some(node_ptr) => node_ptr,
+            None => return false,
+        };
+        unsafe {
+            f(&mut (*node_ptr.as_ptr()).key);
+            let key = &node_ptr.as_ref().key;
+            debug_assert!(
+                Node::predecessor(node_ptr).is_none_or(|prev_ptr| prev_ptr.as_ref().key < *key),
+                "key ordering changed in place"
+            );
+            debug_assert!(
+                Node::successor(node_ptr).is_none_or(|next_ptr| *key < next_ptr.as_ref().key),
+                "key ordering changed in place"
+            );
+        }
+        true
+    }
+
+    /// Returns mutable references to the values corresponding to two distinct keys.
+    /// Returns None if either key is not in the map or if both keys are equal.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn get2_mut<Q>(&mut self, a: &Q, b: &Q) -> Option<(&mut V, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let a_ptr = self.find(a)?;
+        let b_ptr = self.find(b)?;
+        if a_ptr == b_ptr {
+            return None;
+        }
+        // Distinct nodes, so the mutable references do not alias
+        unsafe { Some((&mut (*a_ptr.as_ptr()).value, &mut (*b_ptr.as_ptr()).value)) }
+    }
+
     /// Returns references to the key-value pair corresponding to the key.
     ///
     /// The key may be any borrowed form of the map's key type, but the ordering
@@ -213,6 +655,91 @@ impl<K, V> AvlTreeMap<K, V> {
         ))
     }
 
+    /// Returns references to the first key-value pair in the map, i.e. the one with the minimum key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let node_ptr = self.find_first()?;
+        Some((
+            &unsafe { &*node_ptr.as_ptr() }.key,
+            &unsafe { &*node_ptr.as_ptr() }.value,
+        ))
+    }
+
+    /// Returns references to the last key-value pair in the map, i.e. the one with the maximum key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let node_ptr = self.find_last()?;
+        Some((
+            &unsafe { &*node_ptr.as_ptr() }.key,
+            &unsafe { &*node_ptr.as_ptr() }.value,
+        ))
+    }
+
+    /// Returns the key and a mutable reference to the value of the first entry in the map,
+    /// i.e. the one with the minimum key.
+    pub fn first_key_value_mut(&mut self) -> Option<(&K, &mut V)> {
+        let node_ptr = self.find_first()?;
+        let node = unsafe { &mut *node_ptr.as_ptr() };
+        Some((&node.key, &mut node.value))
+    }
+
+    /// Returns the key and a mutable reference to the value of the last entry in the map,
+    /// i.e. the one with the maximum key.
+    pub fn last_key_value_mut(&mut self) -> Option<(&K, &mut V)> {
+        let node_ptr = self.find_last()?;
+        let node = unsafe { &mut *node_ptr.as_ptr() };
+        Some((&node.key, &mut node.value))
+    }
+
+    /// Returns the entry with the greatest key less than or equal to the given key, if any,
+    /// or else the entry with the least key greater than the given key.
+    ///
+    /// The tree only knows the ordering of keys, not their distance, see [`nearest_by`]
+    /// for picking the closer one of both neighbors.
+    ///
+    /// [`nearest_by`]: #method.nearest_by
+    pub fn nearest<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range_to(Bound::Included(key))
+            .next_back()
+            .or_else(|| self.range_from(Bound::Included(key)).next())
+    }
+
+    /// Returns the entry whose key is closest to the given key, measured by `dist`.
+    /// Only the greatest key less than or equal to the given key (the floor) and
+    /// the least key greater than or equal to it (the ceil) are compared.
+    /// Ties are resolved in favor of the floor.
+    ///
+    /// ```
+    /// use avl::AvlTreeMap;
+    /// let map: AvlTreeMap<_, _> = [(10, 'a'), (20, 'b')].into_iter().collect();
+    /// let dist = |query: &i32, key: &i32| query.abs_diff(*key);
+    /// assert_eq!(map.nearest_by(&14, dist), Some((&10, &'a')));
+    /// assert_eq!(map.nearest_by(&16, dist), Some((&20, &'b')));
+    /// ```
+    pub fn nearest_by<Q, D, F>(&self, key: &Q, mut dist: F) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        D: Ord,
+        F: FnMut(&Q, &K) -> D,
+    {
+        match (
+            self.range_to(Bound::Included(key)).next_back(),
+            self.range_from(Bound::Included(key)).next(),
+        ) {
+            (Some(floor), Some(ceil)) => {
+                if dist(key, ceil.0) < dist(key, floor.0) {
+                    Some(ceil)
+                } else {
+                    Some(floor)
+                }
+            }
+            (floor, ceil) => floor.or(ceil),
+        }
+    }
+
     /// Returns true if the key is in the map, else false.
     ///
     /// The key may be any borrowed form of the map's key type, but the ordering
@@ -225,6 +752,26 @@ impl<K, V> AvlTreeMap<K, V> {
         self.find(key).is_some()
     }
 
+    /// Returns true if any entry of the map has the value, else false.
+    ///
+    /// The values are not ordered, so this scans all entries in linear time.
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.find_by_value(value).is_some()
+    }
+
+    /// Returns the key of the first entry with the value, in order by key.
+    ///
+    /// The values are not ordered, so this scans the entries in linear time.
+    pub fn find_by_value(&self, value: &V) -> Option<&K>
+    where
+        V: PartialEq,
+    {
+        self.iter().find(|(_, v)| *v == value).map(|(k, _)| k)
+    }
+
     /// Inserts a key-value pair into the map.
     /// Returns None if the key is not in the map.
     /// Updates the value if the key is already in the map and returns the old value.
@@ -243,45 +790,224 @@ impl<K, V> AvlTreeMap<K, V> {
         }
     }
 
-    /// Gets the map entry of given key for in-place manipulation.
-    pub fn entry(&mut self, key: K) -> Entry<'_, K, V>
+    /// Inserts a key-value pair into the map like `insert`,
+    /// and also reports how much rebalancing work the insert caused.
+    pub fn insert_with_stats(&mut self, key: K, value: V) -> (Option<V>, InsertStats)
     where
         K: Ord,
     {
-        let mut parent: Link<K, V> = None;
-        let mut link_ptr: LinkPtr<K, V> = unsafe { LinkPtr::new_unchecked(&mut self.root) };
-        unsafe {
-            while let Some(mut node_ptr) = link_ptr.as_ref() {
-                if key == node_ptr.as_ref().key {
-                    // Found key in the map -> return occupied entry
-                    return Entry::Occupied(OccupiedEntry {
-                        map: self,
-                        node_ptr,
-                        marker: PhantomData,
-                    });
-                } else {
-                    parent = *link_ptr.as_ref();
-                    if key < node_ptr.as_ref().key {
-                        link_ptr = LinkPtr::new_unchecked(&mut node_ptr.as_mut().left);
-                    } else {
-                        link_ptr = LinkPtr::new_unchecked(&mut node_ptr.as_mut().right);
-                    }
-                }
-            }
+        match self.find_insert_pos(&key) {
+            InsertPos::Vacant { parent, link_ptr } => unsafe {
+                let (_, stats) = self.insert_entry_at_vacant_pos(parent, link_ptr, key, value);
+                (None, stats)
+            },
+            InsertPos::Occupied { node_ptr } => unsafe {
+                let value = self.insert_value_at_occupied_pos(node_ptr, value);
+                (Some(value), InsertStats::default())
+            },
         }
-
-        // Key is not in the map -> return vacant entry
-        Entry::Vacant(VacantEntry {
-            map: self,
-            parent,
-            insert_pos: link_ptr,
-            key,
-            marker: PhantomData,
-        })
     }
 
-    /// Removes a key from the map.
-    /// Returns the value at the key if the key was previously in the map.
+    /// Inserts a key-value pair into the map like `insert`,
+    /// and also reports whether the insert rotated nodes of the tree,
+    /// e.g. to invalidate cached positions.
+    pub fn insert_checked(&mut self, key: K, value: V) -> InsertResult<V>
+    where
+        K: Ord,
+    {
+        match self.insert_with_stats(key, value) {
+            (Some(value), _) => InsertResult::Updated(value),
+            (None, stats) => InsertResult::Inserted {
+                rotated: stats.rotated,
+            },
+        }
+    }
+
+    /// Creates a map from the key-value pairs of the iterator, keeping the first value
+    /// of duplicate keys.
+    ///
+    /// Collecting into a map with `collect` keeps the last value of duplicate keys instead.
+    ///
+    /// ```
+    /// use avl::AvlTreeMap;
+    /// let map = AvlTreeMap::from_iter_first([(1, "a"), (1, "b")]);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// let map: AvlTreeMap<_, _> = [(1, "a"), (1, "b")].into_iter().collect();
+    /// assert_eq!(map.get(&1), Some(&"b"));
+    /// ```
+    pub fn from_iter_first<I>(iter: I) -> Self
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new();
+        for (key, value) in iter {
+            map.entry(key).or_insert(value);
+        }
+        map
+    }
+
+    /// Inserts all key-value pairs of the iterator into the map.
+    /// Returns the number of keys that were not in the map before.
+    /// Updates the values of keys that are already in the map.
+    pub fn insert_many<I>(&mut self, iter: I) -> usize
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let num_nodes = self.num_nodes;
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+        self.num_nodes - num_nodes
+    }
+
+    /// Clones the key-value pairs of the iterator into the map, like `extend`.
+    ///
+    /// Unlike the `Extend` implementation for references, this only requires `Clone`.
+    pub fn extend_cloned<'a, I>(&mut self, iter: I)
+    where
+        K: Ord + Clone + 'a,
+        V: Clone + 'a,
+        I: IntoIterator<Item = (&'a K, &'a V)>,
+    {
+        self.extend(
+            iter.into_iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+    }
+
+    /// Creates a builder for a map entry looked up by a borrowed key.
+    ///
+    /// Unlike [`entry`], the owned key only needs to be provided if the entry is vacant.
+    ///
+    /// [`entry`]: #method.entry
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V> {
+        RawEntryBuilderMut { map: self }
+    }
+
+    /// Gets the map entry of given key for in-place manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V>
+    where
+        K: Ord,
+    {
+        let mut parent: Link<K, V> = None;
+        let mut link_ptr: LinkPtr<K, V> = unsafe { LinkPtr::new_unchecked(&mut self.root) };
+        unsafe {
+            while let Some(mut node_ptr) = link_ptr.as_ref() {
+                if key == node_ptr.as_ref().key {
+                    // Found key in the map -> return occupied entry
+                    return Entry::Occupied(OccupiedEntry {
+                        map: self,
+                        node_ptr,
+                        marker: PhantomData,
+                    });
+                } else {
+                    parent = *link_ptr.as_ref();
+                    if key < node_ptr.as_ref().key {
+                        link_ptr = LinkPtr::new_unchecked(&mut node_ptr.as_mut().left);
+                    } else {
+                        link_ptr = LinkPtr::new_unchecked(&mut node_ptr.as_mut().right);
+                    }
+                }
+            }
+        }
+
+        // Key is not in the map -> return vacant entry
+        Entry::Vacant(VacantEntry {
+            map: self,
+            parent,
+            insert_pos: link_ptr,
+            key,
+            marker: PhantomData,
+        })
+    }
+
+    /// Returns a mutable reference to the value of the key,
+    /// calling the fallible `make` to create and insert the value if the key is not in the map.
+    ///
+    /// `make` is not called if the key is already in the map.
+    /// If `make` returns an error, the map is left unchanged and the error is returned.
+    pub fn get_or_try_insert_with<E, F>(&mut self, key: K, make: F) -> Result<&mut V, E>
+    where
+        K: Ord,
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self.entry(key) {
+            Entry::Occupied(o) => Ok(o.into_mut()),
+            Entry::Vacant(v) => Ok(v.insert(make()?)),
+        }
+    }
+
+    /// Inserts or updates the value of the key and returns a mutable reference to it.
+    ///
+    /// Calls `f(None)` to create the value if the key is not in the map,
+    /// or `f(Some(old_value))` to compute the new value from the existing one.
+    /// If `f` panics while updating, the entry is removed from the map.
+    pub fn upsert<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        K: Ord,
+        F: FnOnce(Option<V>) -> V,
+    {
+        // Removes the entry if updating panics, its value has been moved out already
+        struct DropGuard<'a, K, V> {
+            map: &'a mut AvlTreeMap<K, V>,
+            node_ptr: NodePtr<K, V>,
+        }
+
+        impl<K, V> Drop for DropGuard<'_, K, V> {
+            fn drop(&mut self) {
+                let (_, value) = unsafe { self.map.remove_entry_at_occupied_pos(self.node_ptr) };
+                mem::forget(value);
+            }
+        }
+
+        match self.find_insert_pos(&key) {
+            InsertPos::Vacant { parent, link_ptr } => unsafe {
+                let value = f(None);
+                let (node_ptr, _) = self.insert_entry_at_vacant_pos(parent, link_ptr, key, value);
+                &mut (*node_ptr.as_ptr()).value
+            },
+            InsertPos::Occupied { node_ptr } => unsafe {
+                let value_ptr = ptr::addr_of_mut!((*node_ptr.as_ptr()).value);
+                let guard = DropGuard {
+                    map: self,
+                    node_ptr,
+                };
+                let value = f(Some(value_ptr.read()));
+                mem::forget(guard);
+                value_ptr.write(value);
+                &mut *value_ptr
+            },
+        }
+    }
+
+    /// Inserts a default value for each key of the iterator that is not yet in the map.
+    /// Leaves the values of keys that are already in the map untouched.
+    ///
+    /// Keys greater than the last key of the map are appended without a search,
+    /// so pre-populating a map with keys in ascending order takes a single pass.
+    pub fn ensure_range<I>(&mut self, keys: I)
+    where
+        K: Ord,
+        V: Default,
+        I: IntoIterator<Item = K>,
+    {
+        for key in keys {
+            match self.max {
+                Some(mut max_ptr) if unsafe { key > max_ptr.as_ref().key } => unsafe {
+                    let insert_pos = LinkPtr::new_unchecked(&mut max_ptr.as_mut().right);
+                    self.insert_entry_at_vacant_pos(Some(max_ptr), insert_pos, key, V::default());
+                },
+                _ => {
+                    self.entry(key).or_default();
+                }
+            }
+        }
+    }
+
+    /// Removes a key from the map.
+    /// Returns the value at the key if the key was previously in the map.
     ///
     /// The key may be any borrowed form of the map's key type, but the ordering
     /// on the borrowed form *must* match the ordering on the key type.
@@ -309,19 +1035,185 @@ impl<K, V> AvlTreeMap<K, V> {
         Some(kv)
     }
 
+    /// Removes a key from the map.
+    /// Returns the stored key and value if the key was previously in the map,
+    /// together with a clone of the next greater key in the map, if any.
+    /// This allows to continue a walk over the map after a removal.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn remove_and_successor<Q>(&mut self, key: &Q) -> (Option<(K, V)>, Option<K>)
+    where
+        K: Borrow<Q> + Clone,
+        Q: Ord + ?Sized,
+    {
+        match self.find(key) {
+            Some(node_ptr) => {
+                let successor = Node::successor(node_ptr)
+                    .map(|next_ptr| unsafe { next_ptr.as_ref().key.clone() });
+                let kv = unsafe { self.remove_entry_at_occupied_pos(node_ptr) };
+                (Some(kv), successor)
+            }
+            None => {
+                let successor = self
+                    .find_start_bound_excluded(key)
+                    .map(|next_ptr| unsafe { next_ptr.as_ref().key.clone() });
+                (None, successor)
+            }
+        }
+    }
+
+    /// Removes all given keys from the map and returns the number of removed entries.
+    ///
+    /// The keys have to be sorted in ascending order. They are matched against the map
+    /// in a single pass, which is faster than calling [`remove`] for large batches.
+    ///
+    /// [`remove`]: #method.remove
+    pub fn remove_all<'a, Q, I>(&mut self, sorted_keys: I) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        const MAX_STEPS: usize = 2;
+        let mut current = self.find_first();
+        let mut prev_key: Option<&Q> = None;
+        let mut num_removed = 0;
+        for key in sorted_keys {
+            debug_assert!(prev_key <= Some(key), "keys are not sorted");
+            prev_key = Some(key);
+            // Step through close keys, seek from the root if the key is further ahead
+            let mut steps = 0;
+            while let Some(node_ptr) = current {
+                match key.cmp(unsafe { node_ptr.as_ref().key.borrow() }) {
+                    Ordering::Equal => {
+                        current = Node::successor(node_ptr);
+                        unsafe {
+                            self.remove_entry_at_occupied_pos(node_ptr);
+                        }
+                        num_removed += 1;
+                        break;
+                    }
+                    Ordering::Less => break,
+                    Ordering::Greater if steps < MAX_STEPS => {
+                        current = Node::successor(node_ptr);
+                        steps += 1;
+                    }
+                    Ordering::Greater => current = self.find_start_bound_included(key),
+                }
+            }
+            if current.is_none() {
+                break;
+            }
+        }
+        num_removed
+    }
+
+    /// Removes the first entry from the map, i.e. the one with the minimum key.
+    /// Returns its key and value if the map was not empty.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let node_ptr = self.find_first()?;
+        Some(unsafe { self.remove_entry_at_occupied_pos(node_ptr) })
+    }
+
+    /// Removes the last entry from the map, i.e. the one with the maximum key.
+    /// Returns its key and value if the map was not empty.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let node_ptr = self.find_last()?;
+        Some(unsafe { self.remove_entry_at_occupied_pos(node_ptr) })
+    }
+
+    /// Removes the first entry from the map if it satisfies the predicate.
+    /// Returns its key and value if it was removed, otherwise the map is left unchanged.
+    pub fn pop_first_if<F>(&mut self, pred: F) -> Option<(K, V)>
+    where
+        F: FnOnce(&K, &V) -> bool,
+    {
+        let node_ptr = self.find_first()?;
+        unsafe {
+            let node = &*node_ptr.as_ptr();
+            if !pred(&node.key, &node.value) {
+                return None;
+            }
+            Some(self.remove_entry_at_occupied_pos(node_ptr))
+        }
+    }
+
+    /// Removes the last entry from the map if it satisfies the predicate.
+    /// Returns its key and value if it was removed, otherwise the map is left unchanged.
+    pub fn pop_last_if<F>(&mut self, pred: F) -> Option<(K, V)>
+    where
+        F: FnOnce(&K, &V) -> bool,
+    {
+        let node_ptr = self.find_last()?;
+        unsafe {
+            let node = &*node_ptr.as_ptr();
+            if !pred(&node.key, &node.value) {
+                return None;
+            }
+            Some(self.remove_entry_at_occupied_pos(node_ptr))
+        }
+    }
+
     /// Moves all elements from other into self, leaving other empty.
+    /// If a key of `other` is already in `self`, the value from `other` overwrites it.
+    ///
+    /// Takes O(log n) time if all keys of `other` are greater than the keys of `self`
+    /// or all are less. Otherwise, the entries of `other` are merged into `self`.
+    ///
+    /// A map cannot be appended to itself, the borrow checker rejects the aliasing:
+    ///
+    /// ```compile_fail
+    /// let mut map = avl::AvlTreeMap::<i32, i32>::new();
+    /// map.append(&mut map);
+    /// ```
     pub fn append(&mut self, other: &mut Self)
     where
         K: Ord,
     {
-        // Check if map is empty
+        // Check if one of the maps is empty
+        if other.is_empty() {
+            return;
+        }
         if self.is_empty() {
             // Move all entries from other into self
             mem::swap(self, other);
             return;
         }
 
-        let mut node_eater = NodeEater::new(mem::replace(other, Self::new()));
+        // Check if key ranges are disjoint, then join the trees around the first or last node
+        // of other, which is removed from other beforehand
+        unsafe {
+            let (self_min_ptr, self_max_ptr) = (self.min.unwrap(), self.max.unwrap());
+            let (other_min_ptr, other_max_ptr) = (other.min.unwrap(), other.max.unwrap());
+            if self_max_ptr.as_ref().key < other_min_ptr.as_ref().key {
+                other.unlink_node(other_min_ptr);
+                self.root = Some(Self::join(self.root, other_min_ptr, other.root.take()));
+                self.max = Some(other_max_ptr);
+                self.num_nodes += mem::take(&mut other.num_nodes);
+                other.min = None;
+                other.max = None;
+                return;
+            }
+            if other_max_ptr.as_ref().key < self_min_ptr.as_ref().key {
+                other.unlink_node(other_max_ptr);
+                self.root = Some(Self::join(other.root.take(), other_max_ptr, self.root));
+                self.min = Some(other_min_ptr);
+                self.num_nodes += mem::take(&mut other.num_nodes);
+                other.min = None;
+                other.max = None;
+                return;
+            }
+        }
+
+        // Merge a large map, insert the entries of a small one
+        if other.num_nodes >= self.num_nodes / 16 {
+            let batch: Vec<(K, V)> = other.drain().collect();
+            self.merge_sorted(batch);
+            return;
+        }
+
+        let mut node_eater = NodeEater::new(mem::take(other));
         while let Some(node_ptr) = node_eater.pop_first_node() {
             unsafe {
                 self.insert_node(node_ptr);
@@ -329,55 +1221,460 @@ impl<K, V> AvlTreeMap<K, V> {
         }
     }
 
-    /// Splits the collection into two at the given key. Returns everything after the given key,
-    /// including the key.
-    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    /// Appends entries with keys greater than all keys of the map, e.g. to add new data
+    /// to a map keyed by timestamp.
+    ///
+    /// The entries have to be in strictly ascending key order and their keys have to be greater
+    /// than the last key of the map, which is checked in debug builds only. The entries are
+    /// linked into a balanced tree, which is joined with the tree of the map. This takes
+    /// O(m + log n) time for m entries, compared to O(m log(n + m)) of inserting them one by one.
+    pub fn extend_sorted_greater<I>(&mut self, iter: I)
+    where
+        K: Ord,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let batch: Vec<(K, V)> = iter.into_iter().collect();
+        debug_assert!(
+            batch.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "keys are not in strictly ascending order"
+        );
+        debug_assert!(
+            match (self.max, batch.first()) {
+                (Some(max_ptr), Some((key, _))) => unsafe { max_ptr.as_ref().key < *key },
+                _ => true,
+            },
+            "keys are not greater than the last key of the map"
+        );
+
+        let mut batch = batch.into_iter();
+        let Some((key, value)) = batch.next() else {
+            return;
+        };
+        let mid_ptr = self.create_node(None, key, value);
+        let nodes: Vec<_> = batch
+            .map(|(key, value)| self.create_node(None, key, value))
+            .collect();
+        unsafe {
+            let right = Self::link_balanced(&nodes, None);
+            self.root = Some(Self::join(self.root, mid_ptr, right));
+        }
+        self.min = self.min.or(Some(mid_ptr));
+        self.max = Some(nodes.last().copied().unwrap_or(mid_ptr));
+        self.num_nodes += 1 + nodes.len();
+    }
+
+    /// Splits the map into two at the given key. Returns the entries before the given key
+    /// and the entries after the given key, including the key.
+    ///
+    /// Takes O(log n) time like [`split_off`] and additional time linear
+    /// in the size of the smaller part.
+    ///
+    /// [`split_off`]: #method.split_off
+    pub fn split_at<Q>(mut self, key: &Q) -> (Self, Self)
+    where
+        K: Ord + Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let offsplit = self.split_off(key);
+        (self, offsplit)
+    }
+
+    /// Splits the collection into two at the given key. Returns everything after the given key,
+    /// including the key.
+    ///
+    /// The tree is cut along the search path of the key and the pieces are joined back into two
+    /// balanced trees, which takes O(log n) time. Counting the entries of both parts takes
+    /// additional time linear in the size of the smaller part.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Ord + Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.split_off_where(|map_key| map_key.borrow() < key)
+    }
+
+    /// Removes the entries within the given range from the map and returns them as a new map.
+    ///
+    /// The tree is split at both ends of the range and the remaining parts are joined back,
+    /// which takes O(log n) time plus the time to count the entries of the smaller parts,
+    /// see [`split_off`].
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`.
+    /// Panics if range `start == end` and both bounds are `Excluded`.
+    ///
+    /// [`split_off`]: #method.split_off
+    pub fn remove_range<Q, R>(&mut self, range: R) -> Self
+    where
+        K: Ord + Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        if let Err(error) = Self::check_range(&range) {
+            panic!("{}", error);
+        }
+        let mut removed = self.split_off_where(|key| match range.start_bound() {
+            Bound::Included(start) => key.borrow() < start,
+            Bound::Excluded(start) => key.borrow() <= start,
+            Bound::Unbounded => false,
+        });
+        let mut greater = removed.split_off_where(|key| match range.end_bound() {
+            Bound::Included(end) => key.borrow() <= end,
+            Bound::Excluded(end) => key.borrow() < end,
+            Bound::Unbounded => true,
+        });
+        self.append(&mut greater);
+        removed
+    }
+
+    /// Splits off the entries whose keys are not less than the split point, i.e. for which
+    /// `is_less` returns false. `is_less` must be true for a prefix of the keys in order.
+    fn split_off_where<F>(&mut self, mut is_less: F) -> Self
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let mut offsplit = Self::new();
+
+        // Check if map is empty or if all map keys are less than the split point
+        if self
+            .find_last()
+            .map(|node_ptr| is_less(unsafe { &node_ptr.as_ref().key }))
+            .unwrap_or(true)
+        {
+            // Nothing to do
+            return offsplit;
+        }
+
+        // Check if no map key is less than the split point
+        if self
+            .find_first()
+            .map(|node_ptr| !is_less(unsafe { &node_ptr.as_ref().key }))
+            .unwrap_or(true)
+        {
+            // Move all entries to split off part leaving self empty
+            mem::swap(self, &mut offsplit);
+            return offsplit;
+        }
+
+        // Record the search path first, so a panicking comparison leaves the map untouched
+        let mut path = Vec::new();
+        let mut current = self.root;
+        while let Some(node_ptr) = current {
+            let node = unsafe { node_ptr.as_ref() };
+            let is_less = is_less(&node.key);
+            path.push((node_ptr, is_less));
+            current = if is_less { node.right } else { node.left };
+        }
+
+        // Join the nodes of the path bottom-up with their sub trees off the path
+        let mut less: Link<K, V> = None;
+        let mut greater: Link<K, V> = None;
+        unsafe {
+            for (node_ptr, is_less) in path.into_iter().rev() {
+                if is_less {
+                    less = Some(Self::join(node_ptr.as_ref().left, node_ptr, less));
+                } else {
+                    greater = Some(Self::join(greater, node_ptr, node_ptr.as_ref().right));
+                }
+            }
+        }
+
+        let num_nodes = self.num_nodes;
+        self.root = less;
+        offsplit.root = greater;
+        offsplit.min = offsplit.walk_first();
+        offsplit.max = self.max;
+        self.max = self.walk_last();
+
+        // Count the smaller part by iterating both parts in lockstep
+        let mut lhs = self.min;
+        let mut rhs = offsplit.min;
+        let mut count = 0;
+        while let (Some(lhs_ptr), Some(rhs_ptr)) = (lhs, rhs) {
+            lhs = Node::successor(lhs_ptr);
+            rhs = Node::successor(rhs_ptr);
+            count += 1;
+        }
+        self.num_nodes = if lhs.is_none() {
+            count
+        } else {
+            num_nodes - count
+        };
+        offsplit.num_nodes = num_nodes - self.num_nodes;
+
+        offsplit
+    }
+
+    /// Partitions the map around the given pivot key.
+    /// Returns the entries less than the pivot, the entry of the pivot if it is in the map,
+    /// and the entries greater than the pivot.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn split_three<Q>(mut self, pivot: &Q) -> (Self, Option<(K, V)>, Self)
+    where
+        K: Ord + Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let mut greater = self.split_off(pivot);
+        let entry = greater.remove_entry(pivot);
+        (self, entry, greater)
+    }
+
+    /// Consumes the map and returns it rebuilt as a tree of minimum height.
+    ///
+    /// The nodes are relinked in place, so no entries are moved or reallocated.
+    /// Useful as a finalization step before a phase of mostly lookups.
+    pub fn compact_into(mut self) -> Self {
+        let mut nodes = Vec::with_capacity(self.num_nodes);
+        self.inorder(|node_ptr| nodes.push(node_ptr));
+        self.root = None;
+        unsafe {
+            self.link_sorted_nodes(&nodes);
+        }
+        self
+    }
+
+    /// Rebuilds the map as a tree of minimum height with freshly allocated nodes.
+    ///
+    /// After many inserts and removes, the nodes may be scattered across the heap.
+    /// Rebuilding moves the entries out in order and allocates new nodes one after another,
+    /// which tends to place them close to each other and speeds up subsequent iteration.
+    /// The memory kept for reuse by removed entries is deallocated. Takes O(n) time.
+    pub fn rebuild(&mut self) {
+        let mut entries = Vec::with_capacity(self.num_nodes);
+        let mut tree = self.take_tree();
+        while let Some(entry) = tree.pop_first() {
+            entries.push(entry);
+        }
+        self.shrink_to_fit();
+        let nodes: Vec<_> = entries
+            .into_iter()
+            .map(|(key, value)| self.create_node(None, key, value))
+            .collect();
+        unsafe {
+            self.link_sorted_nodes(&nodes);
+        }
+    }
+
+    /// Retains only the entries specified by the predicate.
+    ///
+    /// Removes all entries for which `f(&key, &mut value)` returns false.
+    /// The entries are visited in ascending key order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let mut current = self.find_first();
+        while let Some(node_ptr) = current {
+            unsafe {
+                current = Node::successor(node_ptr);
+                let node = &mut *node_ptr.as_ptr();
+                if !f(&node.key, &mut node.value) {
+                    self.remove_entry_at_occupied_pos(node_ptr);
+                }
+            }
+        }
+    }
+
+    /// Creates an iterator which removes and yields the entries for which
+    /// `pred(&key, &mut value)` returns true, in order by key.
+    ///
+    /// The entries are removed lazily while iterating. If the iterator is dropped early,
+    /// the entries not visited yet remain in the map.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf {
+            inner: self.extract_if_inner(),
+            pred,
+        }
+    }
+
+    /// Gets an iterator over a range of elements in the map, in order by key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`.
+    /// Panics if range `start == end` and both bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (first, last) = self.find_range(range);
+        Range {
+            node_iter: unsafe { NodeIter::new(first, last) },
+        }
+    }
+
+    /// Gets an iterator over a range of elements in the map, in descending order by key.
+    /// Same as `range(range).rev()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same invalid ranges as [`range`].
+    ///
+    /// [`range`]: #method.range
+    pub fn range_rev<Q, R>(&self, range: R) -> Rev<Range<'_, K, V>>
+    where
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range(range).rev()
+    }
+
+    /// Gets an iterator over a range of elements in the map, in order by key.
+    ///
+    /// Unlike [`range`], the bounds are of the key type itself,
+    /// so owned keys can be passed as bounds without specifying the borrowed form.
+    ///
+    /// [`range`]: #method.range
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`.
+    /// Panics if range `start == end` and both bounds are `Excluded`.
+    pub fn range_owned<R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Ord,
+        R: RangeBounds<K>,
+    {
+        self.range::<K, R>(range)
+    }
+
+    /// Gets an iterator over the elements of the map starting at given bound, in order by key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn range_from<Q>(&self, start: Bound<&Q>) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range((start, Bound::Unbounded))
+    }
+
+    /// Gets an iterator over the elements of the map up to given bound, in order by key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn range_to<Q>(&self, end: Bound<&Q>) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range((Bound::Unbounded, end))
+    }
+
+    /// Gets an iterator over the elements of the map with keys strictly greater than given key,
+    /// in order by key.
+    ///
+    /// Used to resume an iteration after the last processed key,
+    /// even if elements have been inserted or removed in between.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn range_resume<Q>(&self, after: &Q) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.range_from(Bound::Excluded(after))
+    }
+
+    /// Gets an iterator over the elements around given key, in order by key.
+    /// The range starts at the first element with a key not less than the center key
+    /// and extends by up to `before` elements before and up to `after` elements after it.
+    /// If all keys are less than the center key, the range holds up to `before` last elements.
+    ///
+    /// Fewer elements are returned near the ends of the map. The range is found by walking
+    /// from the center element, which takes O(log n + before + after) time.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn range_around<Q>(&self, center: &Q, before: usize, after: usize) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (first, last) = match self.find_start_bound_included(center) {
+            Some(center_ptr) => {
+                let mut first_ptr = center_ptr;
+                for _ in 0..before {
+                    match Node::predecessor(first_ptr) {
+                        Some(prev_ptr) => first_ptr = prev_ptr,
+                        None => break,
+                    }
+                }
+                let mut last_ptr = center_ptr;
+                for _ in 0..after {
+                    match Node::successor(last_ptr) {
+                        Some(next_ptr) => last_ptr = next_ptr,
+                        None => break,
+                    }
+                }
+                (Some(first_ptr), Some(last_ptr))
+            }
+            None if before == 0 => (None, None),
+            None => match self.find_last() {
+                Some(last_ptr) => {
+                    let mut first_ptr = last_ptr;
+                    for _ in 1..before {
+                        match Node::predecessor(first_ptr) {
+                            Some(prev_ptr) => first_ptr = prev_ptr,
+                            None => break,
+                        }
+                    }
+                    (Some(first_ptr), Some(last_ptr))
+                }
+                None => (None, None),
+            },
+        };
+        Range {
+            node_iter: unsafe { NodeIter::new(first, last) },
+        }
+    }
+
+    /// Gets an iterator over a range of elements in the map, in order by key.
+    /// Returns an error instead of panicking if the range is invalid.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn try_range<Q, R>(&self, range: R) -> Result<Range<'_, K, V>, RangeError>
     where
-        K: Ord + Borrow<Q>,
-        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
     {
-        let mut offsplit = Self::new();
-
-        // Check if map is emptry or if all map keys are less than given key
-        if self
-            .find_last()
-            .map(|node_ptr| unsafe { node_ptr.as_ref().key.borrow() } < key)
-            .unwrap_or(true)
-        {
-            // Nothing to do
-            return offsplit;
-        }
-
-        // Check if all map keys are greater or equal than given key
-        if self
-            .find_first()
-            .map(|node_ptr| unsafe { node_ptr.as_ref().key.borrow() } >= key)
-            .unwrap_or(true)
-        {
-            // Move all entries to split off part leaving self empty
-            mem::swap(self, &mut offsplit);
-            return offsplit;
-        }
+        Self::check_range(&range)?;
+        Ok(self.range(range))
+    }
 
-        let mut node_eater = NodeEater::new(mem::replace(self, Self::new()));
-        unsafe {
-            while let Some(node_ptr) = node_eater.pop_first_node() {
-                if node_ptr.as_ref().key.borrow() < key {
-                    self.insert_node(node_ptr);
-                } else {
-                    offsplit.insert_node(node_ptr);
-                    break;
-                }
-            }
-            while let Some(node_ptr) = node_eater.pop_first_node() {
-                offsplit.insert_node(node_ptr);
-            }
+    /// Clears the map, returning all key-value pairs as an iterator, in order by key.
+    ///
+    /// The map is empty right away. Like `clear`, only keeps the capacity requested
+    /// by `with_capacity` for reuse.
+    /// Elements not consumed by the iterator are dropped when the iterator is dropped.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain {
+            node_eater: self.take_tree(),
+            map: self,
         }
-
-        offsplit
     }
 
-    /// Gets an iterator over a range of elements in the map, in order by key.
+    /// Converts the map into an owning iterator over a range of elements, in order by key.
+    /// The elements outside of the range are dropped.
     ///
     /// The key may be any borrowed form of the map's key type, but the ordering
     /// on the borrowed form *must* match the ordering on the key type.
@@ -386,16 +1683,16 @@ impl<K, V> AvlTreeMap<K, V> {
     ///
     /// Panics if range `start > end`.
     /// Panics if range `start == end` and both bounds are `Excluded`.
-    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    pub fn into_range<Q, R>(self, range: R) -> IntoRange<K, V>
     where
         K: Borrow<Q>,
         R: RangeBounds<Q>,
         Q: Ord + ?Sized,
     {
         let (first, last) = self.find_range(range);
-        Range {
-            node_iter: unsafe { NodeIter::new(first, last) },
-        }
+        let mut node_eater = NodeEater::new(self);
+        node_eater.truncate(first, last);
+        IntoRange { node_eater }
     }
 
     /// Gets a mutable iterator over a range of elements in the map, in order by key.
@@ -422,35 +1719,267 @@ impl<K, V> AvlTreeMap<K, V> {
     /// Gets an iterator over the entries of the map, sorted by key.
     pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
-            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
+        }
+    }
+
+    /// Gets an iterator over the entries of the map, sorted by key,
+    /// together with their zero-based position in the map.
+    pub fn iter_indexed(&self) -> IterIndexed<'_, K, V> {
+        IterIndexed {
+            iter: self.iter(),
+            front: 0,
+            back: self.num_nodes,
+        }
+    }
+
+    /// Gets an iterator over the keys of both maps, sorted by key and without duplicates.
+    /// Each key comes with its value(s) and tells whether it is only in `self`,
+    /// only in `other`, or in both maps.
+    pub fn merge_join<'a>(&'a self, other: &'a Self) -> MergeJoin<'a, K, V>
+    where
+        K: Ord,
+    {
+        MergeJoin {
+            lhs_iter: self.iter(),
+            rhs_iter: other.iter(),
+        }
+    }
+
+    /// Gets an iterator over groups of consecutive entries, in order by key,
+    /// whose keys have equal projections, e.g. events grouped by their day.
+    /// Each group is yielded together with its projection.
+    ///
+    /// Entries with equal projections form a single group if the projection is monotone,
+    /// i.e. preserves the order of the keys.
+    ///
+    /// ```
+    /// use avl::AvlTreeMap;
+    /// let map: AvlTreeMap<_, _> = [(1, 'a'), (5, 'b'), (12, 'c')].into_iter().collect();
+    /// let groups: Vec<_> = map.group_by(|key| key / 10).collect();
+    /// assert_eq!(groups, [(0, vec![(&1, &'a'), (&5, &'b')]), (1, vec![(&12, &'c')])]);
+    /// ```
+    pub fn group_by<Q, F>(&self, proj: F) -> GroupBy<'_, K, V, F>
+    where
+        Q: PartialEq,
+        F: FnMut(&K) -> Q,
+    {
+        GroupBy {
+            iter: self.iter(),
+            proj,
         }
     }
 
     /// Gets an iterator over the keys of the map, in sorted order.
     pub fn keys(&self) -> Keys<'_, K, V> {
         Keys {
-            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
         }
     }
 
     /// Gets an iterator over the values of the map, in order by key.
     pub fn values(&self) -> Values<'_, K, V> {
         Values {
-            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
         }
     }
 
     /// Gets a mutable iterator over the values of the map, in order by key.
-    pub fn values_mut(&self) -> ValuesMut<'_, K, V> {
+    ///
+    /// The iterator borrows the map mutably, so the map cannot be read (e.g. by `keys`)
+    /// while it is alive. To look at the keys while changing the values, use [`iter_mut`]:
+    ///
+    /// ```
+    /// let mut map: avl::AvlTreeMap<_, _> = (0..3).map(|key| (key, 0)).collect();
+    /// for (key, value) in map.iter_mut() {
+    ///     *value = key * 10;
+    /// }
+    /// assert!(map.values().eq(&[0, 10, 20]));
+    /// ```
+    ///
+    /// ```compile_fail
+    /// let mut map: avl::AvlTreeMap<_, _> = (0..3).map(|key| (key, 0)).collect();
+    /// let values = map.values_mut();
+    /// let keys = map.keys();
+    /// for (key, value) in keys.zip(values) {
+    ///     *value = key * 10;
+    /// }
+    /// ```
+    ///
+    /// [`iter_mut`]: #method.iter_mut
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
         ValuesMut {
-            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
+        }
+    }
+
+    /// Gets an iterator over the entries of the map, sorted by key,
+    /// together with the depth of their node in the tree (the root has depth 0).
+    ///
+    /// Computing the depth walks up to the root for each entry, so iterating takes O(n log n).
+    pub fn iter_with_depth(&self) -> IterWithDepth<'_, K, V> {
+        IterWithDepth {
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
         }
     }
 
     /// Gets a mutable iterator over the entries of the map, sorted by key.
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    ///
+    /// Keys are yielded as shared references, since changing their order would break the tree.
+    /// The yielded references may be kept and used together, as each entry is yielded once:
+    ///
+    /// ```
+    /// let mut map: avl::AvlTreeMap<_, _> = (0..4).map(|key| (key, key)).collect();
+    /// let mut entries: Vec<_> = map.iter_mut().collect();
+    /// let (_, last) = entries.pop().unwrap();
+    /// let (_, first) = &mut entries[0];
+    /// std::mem::swap(*first, last);
+    /// assert!(map.values().eq(&[3, 1, 2, 0]));
+    /// ```
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         IterMut {
-            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+            node_iter: unsafe {
+                NodeIter::with_len(self.find_first(), self.find_last(), self.num_nodes)
+            },
+        }
+    }
+
+    /// Gets an iterator over the entries of the map, in descending order by key.
+    /// Same as `iter().rev()`.
+    pub fn iter_rev(&self) -> Rev<Iter<'_, K, V>> {
+        self.iter().rev()
+    }
+
+    /// Gets an iterator over the keys of the map, in descending order.
+    /// Same as `keys().rev()`.
+    pub fn keys_rev(&self) -> Rev<Keys<'_, K, V>> {
+        self.keys().rev()
+    }
+
+    /// Gets an iterator over the values of the map, in descending order by key.
+    /// Same as `values().rev()`.
+    pub fn values_rev(&self) -> Rev<Values<'_, K, V>> {
+        self.values().rev()
+    }
+
+    /// Folds all entries of the map into an accumulator, in order by key.
+    ///
+    /// Equivalent to `iter().fold(init, ...)`, but traverses the tree directly
+    /// instead of constructing an iterator.
+    pub fn fold_in_order<B, F>(&self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, &K, &V) -> B,
+    {
+        let mut acc = Some(init);
+        self.inorder(|node_ptr| {
+            let node = unsafe { node_ptr.as_ref() };
+            acc = acc.take().map(|acc| f(acc, &node.key, &node.value));
+        });
+        acc.unwrap()
+    }
+
+    /// Calls the fallible `f` with the rank, key and mutable value of each entry,
+    /// in order by key. The rank is the zero-based position of the entry in the map.
+    ///
+    /// Stops at the first error and returns it. Values visited before stay modified,
+    /// values after the failed entry are not visited.
+    pub fn try_for_each_ranked_mut<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(usize, &K, &mut V) -> Result<(), E>,
+    {
+        for (rank, (key, value)) in self.iter_mut().enumerate() {
+            f(rank, key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the fallible transformation `f` to all values in the map, in order by key.
+    ///
+    /// Stops at the first error and restores all values to their state before the call,
+    /// so the map is either updated completely or left unchanged.
+    /// Each value is cloned before it is passed to `f` to allow the rollback.
+    pub fn try_map_values_in_place<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        V: Clone,
+        F: FnMut(&K, &mut V) -> Result<(), E>,
+    {
+        let mut originals = Vec::new();
+        let mut result = Ok(());
+        for (key, value) in self.iter_mut() {
+            originals.push(value.clone());
+            result = f(key, value);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if result.is_err() {
+            // Roll back all values passed to f, including the failed one
+            for ((_, value), original) in self.iter_mut().zip(originals) {
+                *value = original;
+            }
+        }
+        result
+    }
+
+    /// Returns a wrapper to show the tree structure of the map with `{:?}`,
+    /// one line per node, indented by depth and annotated with the height of the node.
+    ///
+    /// ```
+    /// use avl::AvlTreeMap;
+    /// let map: AvlTreeMap<_, _> = (1..=3).map(|key| (key, ())).collect();
+    /// assert_eq!(
+    ///     format!("{:?}", map.debug_tree()),
+    ///     "2: () (height 1)\n    L: 1: () (height 0)\n    R: 3: () (height 0)\n"
+    /// );
+    /// ```
+    pub fn debug_tree(&self) -> DebugTree<'_, K, V> {
+        DebugTree { map: self }
+    }
+
+    /// Returns statistics about the shape of the tree, collected by a traversal of all nodes.
+    pub fn stats(&self) -> TreeStats {
+        let mut height = 0;
+        let mut min_depth = usize::MAX;
+        let mut pending = Vec::new();
+        pending.extend(self.root.map(|root_ptr| (root_ptr, 1)));
+        while let Some((node_ptr, depth)) = pending.pop() {
+            let node = unsafe { node_ptr.as_ref() };
+            if node.left.is_none() && node.right.is_none() {
+                height = cmp::max(height, depth);
+                min_depth = cmp::min(min_depth, depth);
+            }
+            pending.extend(node.left.map(|left_ptr| (left_ptr, depth + 1)));
+            pending.extend(node.right.map(|right_ptr| (right_ptr, depth + 1)));
+        }
+
+        // Minimal number of nodes of an AVL tree of given height follows n(h) = n(h-1) + n(h-2) + 1
+        let (mut min_len, mut prev_min_len) = (0usize, 0usize);
+        for level in 0..height {
+            let next_min_len = if level == 0 {
+                1
+            } else {
+                min_len.saturating_add(prev_min_len).saturating_add(1)
+            };
+            prev_min_len = min_len;
+            min_len = next_min_len;
+        }
+
+        TreeStats {
+            len: self.num_nodes,
+            height,
+            min_depth: if height == 0 { 0 } else { min_depth },
+            within_avl_bound: self.num_nodes >= min_len,
         }
     }
 
@@ -502,7 +2031,197 @@ impl<K, V> AvlTreeMap<K, V> {
 
             // Check number of nodes
             assert_eq!(num_nodes, self.num_nodes);
+
+            // Check cached first and last node
+            assert!(self.min == self.walk_first());
+            assert!(self.max == self.walk_last());
+
+            // Check free list
+            let mut num_free = 0;
+            let mut free = self.free;
+            while let Some(node_ptr) = free {
+                free = ptr::addr_of!((*node_ptr.as_ptr()).parent).read();
+                num_free += 1;
+            }
+            assert_eq!(num_free, self.num_free);
+        }
+
+        assert!(self.is_consistent());
+    }
+
+    /// Asserts in debug builds that the cached values of the map match the tree, i.e. the
+    /// number of nodes, the first and last node and the free list.
+    ///
+    /// Lighter than [`check_consistency`], takes O(log n) time and does not compare keys.
+    /// With the `paranoid` feature, this is called after each insert and remove.
+    ///
+    /// [`check_consistency`]: #method.check_consistency
+    pub fn debug_assert_valid(&self) {
+        debug_assert_eq!(
+            self.root.is_none(),
+            self.num_nodes == 0,
+            "cached length is invalid"
+        );
+        debug_assert!(
+            self.root
+                .is_none_or(|root_ptr| unsafe { root_ptr.as_ref().parent.is_none() }),
+            "root has a parent"
+        );
+        debug_assert!(
+            self.min == self.walk_first(),
+            "cached first node is invalid"
+        );
+        debug_assert!(self.max == self.walk_last(), "cached last node is invalid");
+        debug_assert_eq!(
+            self.free.is_none(),
+            self.num_free == 0,
+            "free list count is invalid"
+        );
+    }
+
+    /// Returns `true` if the internal tree structure is consistent, i.e. the keys are
+    /// in strictly ascending order, the tree is nearly balanced and all links and
+    /// cached values are valid.
+    ///
+    /// Unlike [`check_consistency`], this is available in all builds and does not panic.
+    /// An inconsistent tree is the result of a key type with an inconsistent ordering
+    /// or of a bug in this crate.
+    ///
+    /// [`check_consistency`]: #method.check_consistency
+    pub fn is_consistent(&self) -> bool
+    where
+        K: Ord,
+    {
+        unsafe {
+            if let Some(root_ptr) = self.root {
+                if root_ptr.as_ref().parent.is_some() {
+                    return false;
+                }
+            }
+
+            // Walk down the tree without following parent links,
+            // bounding the keys of each node by the keys of its ancestors
+            let mut num_nodes = 0;
+            let mut pending = Vec::new();
+            if let Some(root_ptr) = self.root {
+                pending.push((root_ptr, None::<&K>, None::<&K>));
+            }
+            while let Some((node_ptr, lower, upper)) = pending.pop() {
+                num_nodes += 1;
+                if num_nodes > self.num_nodes {
+                    return false;
+                }
+
+                let node = node_ptr.as_ref();
+                if lower.is_some_and(|lower| node.key <= *lower)
+                    || upper.is_some_and(|upper| node.key >= *upper)
+                {
+                    return false;
+                }
+
+                let mut child_heights = [0usize; 2];
+                for (child, child_height) in
+                    [node.left, node.right].into_iter().zip(&mut child_heights)
+                {
+                    if let Some(child_ptr) = child {
+                        if child_ptr.as_ref().parent != Some(node_ptr) {
+                            return false;
+                        }
+                        *child_height = usize::from(child_ptr.as_ref().height) + 1;
+                    }
+                }
+                let [left_height, right_height] = child_heights;
+                if usize::from(node.height) != cmp::max(left_height, right_height)
+                    || left_height > right_height + 1
+                    || right_height > left_height + 1
+                {
+                    return false;
+                }
+
+                if let Some(left_ptr) = node.left {
+                    pending.push((left_ptr, lower, Some(&node.key)));
+                }
+                if let Some(right_ptr) = node.right {
+                    pending.push((right_ptr, Some(&node.key), upper));
+                }
+            }
+            if num_nodes != self.num_nodes
+                || self.min != self.walk_first()
+                || self.max != self.walk_last()
+            {
+                return false;
+            }
+
+            let mut num_free = 0;
+            let mut free = self.free;
+            while let Some(node_ptr) = free {
+                num_free += 1;
+                if num_free > self.num_free {
+                    return false;
+                }
+                free = ptr::addr_of!((*node_ptr.as_ptr()).parent).read();
+            }
+            num_free == self.num_free
+        }
+    }
+
+    /// Test-only hook setting the cached height of every node to `height`.
+    #[cfg(test)]
+    pub(crate) fn corrupt_heights(&mut self, height: u16) {
+        self.preorder(|mut node_ptr| unsafe { node_ptr.as_mut().height = height });
+    }
+
+    /// Returns `true` if both maps have an identical tree structure,
+    /// i.e. equal keys at the same positions with the same heights.
+    ///
+    /// Unlike `==`, which compares the entries only, this tells apart maps with equal entries
+    /// that were built in different ways.
+    #[cfg(any(test, feature = "consistency_check"))]
+    pub fn same_shape(&self, other: &Self) -> bool
+    where
+        K: PartialEq,
+    {
+        let mut pending = Vec::new();
+        pending.push((self.root, other.root));
+        while let Some(links) = pending.pop() {
+            match links {
+                (None, None) => {}
+                (Some(node_ptr), Some(other_node_ptr)) => unsafe {
+                    let node = node_ptr.as_ref();
+                    let other_node = other_node_ptr.as_ref();
+                    if node.height != other_node.height || node.key != other_node.key {
+                        return false;
+                    }
+                    pending.push((node.left, other_node.left));
+                    pending.push((node.right, other_node.right));
+                },
+                _ => return false,
+            }
         }
+        true
+    }
+}
+
+/// Multimap helpers for maps with vectors of values.
+/// Requires the `multimap` feature.
+#[cfg(feature = "multimap")]
+impl<K: Ord, V> AvlTreeMap<K, Vec<V>> {
+    /// Appends the value to the values of the key, creating the entry if the key is not in the map.
+    pub fn push(&mut self, key: K, value: V) {
+        self.entry(key).or_default().push(value);
+    }
+
+    /// Returns the values of the key, in the order they have been pushed.
+    /// Returns an empty slice if the key is not in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn get_all<Q>(&self, key: &Q) -> &[V]
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).map_or(&[], Vec::as_slice)
     }
 }
 // endregion Public implementation of AvlTreeMap
@@ -555,31 +2274,36 @@ impl<K, V> AvlTreeMap<K, V> {
         InsertPos::Vacant { parent, link_ptr }
     }
 
-    fn find_range<Q, R>(&self, range: R) -> (Link<K, V>, Link<K, V>)
+    fn check_range<Q, R>(range: &R) -> Result<(), RangeError>
     where
-        K: Borrow<Q>,
         R: RangeBounds<Q>,
         Q: Ord + ?Sized,
     {
-        // Check for invalid range
         match (range.start_bound(), range.end_bound()) {
             (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
-                panic!("range start and end are equal and excluded")
-            }
-            (Bound::Included(s), Bound::Included(e)) if s > e => {
-                panic!("range start is greater than range end")
-            }
-            (Bound::Excluded(s), Bound::Included(e)) if s > e => {
-                panic!("range start is greater than range end")
-            }
-            (Bound::Included(s), Bound::Excluded(e)) if s > e => {
-                panic!("range start is greater than range end")
+                Err(RangeError::EmptyExcludedExcluded)
             }
-            (Bound::Excluded(s), Bound::Excluded(e)) if s > e => {
-                panic!("range start is greater than range end")
+            (Bound::Included(s), Bound::Included(e))
+            | (Bound::Excluded(s), Bound::Included(e))
+            | (Bound::Included(s), Bound::Excluded(e))
+            | (Bound::Excluded(s), Bound::Excluded(e))
+                if s > e =>
+            {
+                Err(RangeError::StartGreaterThanEnd)
             }
-            _ => {}
-        };
+            _ => Ok(()),
+        }
+    }
+
+    fn find_range<Q, R>(&self, range: R) -> (Link<K, V>, Link<K, V>)
+    where
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        if let Err(err) = Self::check_range(&range) {
+            panic!("{}", err);
+        }
 
         let mut first = match range.start_bound() {
             Bound::Unbounded => self.find_first(),
@@ -602,22 +2326,83 @@ impl<K, V> AvlTreeMap<K, V> {
                 first_ptr.as_ref().key.borrow() > last_ptr.as_ref().key.borrow()
             },
         };
-
+
+        if is_empty_range {
+            first = None;
+            last = None;
+        }
+
+        (first, last)
+    }
+
+    /// Gets a range over the first `n` entries of the map, or all entries if there are less.
+    pub(crate) fn range_first_n(&self, n: usize) -> Range<'_, K, V> {
+        let mut last = None;
+        let mut current = self.find_first();
+        for _ in 0..n {
+            match current {
+                None => break,
+                Some(node_ptr) => {
+                    last = Some(node_ptr);
+                    current = Node::successor(node_ptr);
+                }
+            }
+        }
+        let first = last.and(self.find_first());
+        Range {
+            node_iter: unsafe { NodeIter::new(first, last) },
+        }
+    }
+
+    /// Gets a range over the last `n` entries of the map, or all entries if there are less.
+    pub(crate) fn range_last_n(&self, n: usize) -> Range<'_, K, V> {
+        let mut first = None;
+        let mut current = self.find_last();
+        for _ in 0..n {
+            match current {
+                None => break,
+                Some(node_ptr) => {
+                    first = Some(node_ptr);
+                    current = Node::predecessor(node_ptr);
+                }
+            }
+        }
+        let last = first.and(self.find_last());
+        Range {
+            node_iter: unsafe { NodeIter::new(first, last) },
+        }
+    }
+
+    /// Moves the start of the range to the first key not less than given key.
+    /// The range becomes empty if there is no such key in the range.
+    pub(crate) fn reset_range_start_bound_included<Q>(&self, range: &mut Range<'_, K, V>, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let range_iter = &mut range.node_iter;
+        range_iter.first = self.find_start_bound_included(key);
+        let is_empty_range = match (range_iter.first, range_iter.last) {
+            (None, _) | (_, None) => true,
+            (Some(first_ptr), Some(last_ptr)) => unsafe {
+                first_ptr.as_ref().key.borrow() > last_ptr.as_ref().key.borrow()
+            },
+        };
         if is_empty_range {
-            first = None;
-            last = None;
+            range_iter.first = None;
+            range_iter.last = None;
         }
-
-        (first, last)
     }
 
-    pub(crate) fn reset_range_start_bound_included<Q>(&self, range: &mut Range<'_, K, V>, key: &Q)
+    /// Moves the end of the range to the last key not greater than given key.
+    /// The range becomes empty if there is no such key in the range.
+    pub(crate) fn reset_range_end_bound_included<Q>(&self, range: &mut Range<'_, K, V>, key: &Q)
     where
         K: Borrow<Q>,
         Q: Ord + ?Sized,
     {
         let range_iter = &mut range.node_iter;
-        range_iter.first = self.find_start_bound_included(key);
+        range_iter.last = self.find_end_bound_included(key);
         let is_empty_range = match (range_iter.first, range_iter.last) {
             (None, _) | (_, None) => true,
             (Some(first_ptr), Some(last_ptr)) => unsafe {
@@ -765,6 +2550,15 @@ impl<K, V> AvlTreeMap<K, V> {
     }
 
     fn find_first(&self) -> Link<K, V> {
+        self.min
+    }
+
+    fn find_last(&self) -> Link<K, V> {
+        self.max
+    }
+
+    /// Finds the first node by walking down the left spine of the tree.
+    fn walk_first(&self) -> Link<K, V> {
         let mut min_ptr = self.root?;
         while let Some(left_ptr) = unsafe { min_ptr.as_ref().left } {
             min_ptr = left_ptr;
@@ -772,7 +2566,8 @@ impl<K, V> AvlTreeMap<K, V> {
         Some(min_ptr)
     }
 
-    fn find_last(&self) -> Link<K, V> {
+    /// Finds the last node by walking down the right spine of the tree.
+    fn walk_last(&self) -> Link<K, V> {
         let mut max_ptr = self.root?;
         while let Some(right_ptr) = unsafe { max_ptr.as_ref().right } {
             max_ptr = right_ptr;
@@ -780,20 +2575,45 @@ impl<K, V> AvlTreeMap<K, V> {
         Some(max_ptr)
     }
 
+    /// Updates cached first and last node after given node has been linked into the tree as leaf.
+    fn update_min_max_for_linked(&mut self, node_ptr: NodePtr<K, V>) {
+        match unsafe { node_ptr.as_ref().parent } {
+            None => {
+                self.min = Some(node_ptr);
+                self.max = Some(node_ptr);
+            }
+            Some(parent_ptr) => {
+                if self.min == Some(parent_ptr)
+                    && unsafe { parent_ptr.as_ref().left } == Some(node_ptr)
+                {
+                    self.min = Some(node_ptr);
+                } else if self.max == Some(parent_ptr)
+                    && unsafe { parent_ptr.as_ref().right } == Some(node_ptr)
+                {
+                    self.max = Some(node_ptr);
+                }
+            }
+        }
+    }
+
     unsafe fn insert_entry_at_vacant_pos(
         &mut self,
         parent: Link<K, V>,
         mut insert_pos: LinkPtr<K, V>,
         key: K,
         value: V,
-    ) -> &mut V {
-        let node_ptr = Node::create(parent, key, value);
+    ) -> (NodePtr<K, V>, InsertStats) {
+        let node_ptr = self.create_node(parent, key, value);
         *insert_pos.as_mut() = Some(node_ptr);
-        if let Some(parent_ptr) = parent {
-            self.rebalance_once(parent_ptr);
-        }
+        self.update_min_max_for_linked(node_ptr);
+        let stats = match parent {
+            None => InsertStats::default(),
+            Some(parent_ptr) => self.rebalance_once(parent_ptr),
+        };
         self.num_nodes += 1;
-        &mut (*node_ptr.as_ptr()).value
+        #[cfg(feature = "paranoid")]
+        self.debug_assert_valid();
+        (node_ptr, stats)
     }
 
     unsafe fn insert_value_at_occupied_pos(
@@ -805,11 +2625,55 @@ impl<K, V> AvlTreeMap<K, V> {
         value
     }
 
+    /// Removes given node from the tree and returns its key and value.
+    ///
+    /// The other nodes are not moved in memory and keep their order, so pointers to them,
+    /// e.g. to the successor of the removed node, stay valid.
     unsafe fn remove_entry_at_occupied_pos(&mut self, node_ptr: NodePtr<K, V>) -> (K, V) {
         debug_assert!(self.num_nodes > 0);
         self.num_nodes -= 1;
         self.unlink_node(node_ptr);
-        Node::destroy(node_ptr)
+        #[cfg(feature = "paranoid")]
+        self.debug_assert_valid();
+        self.recycle_node(node_ptr)
+    }
+
+    /// Creates a node, reusing a node of the free list if available.
+    fn create_node(&mut self, parent: Link<K, V>, key: K, value: V) -> NodePtr<K, V> {
+        match self.free {
+            None => Node::create(parent, key, value),
+            Some(node_ptr) => unsafe {
+                self.free = ptr::addr_of!((*node_ptr.as_ptr()).parent).read();
+                self.num_free -= 1;
+                node_ptr.as_ptr().write(Node {
+                    parent,
+                    left: None,
+                    right: None,
+                    height: 0,
+                    key,
+                    value,
+                });
+                node_ptr
+            },
+        }
+    }
+
+    /// Moves key and value out of given unlinked node and puts the node on the free list,
+    /// or deallocates it if the map would exceed the capacity requested by `with_capacity`.
+    unsafe fn recycle_node(&mut self, node_ptr: NodePtr<K, V>) -> (K, V) {
+        if self.num_nodes + self.num_free >= self.reserved {
+            return Node::destroy(node_ptr);
+        }
+        let node = node_ptr.as_ptr().read();
+        self.push_free_node(node_ptr);
+        (node.key, node.value)
+    }
+
+    /// Puts given node with uninitialized key and value on the free list.
+    unsafe fn push_free_node(&mut self, node_ptr: NodePtr<K, V>) {
+        ptr::addr_of_mut!((*node_ptr.as_ptr()).parent).write(self.free);
+        self.free = Some(node_ptr);
+        self.num_free += 1;
     }
 
     unsafe fn insert_node(&mut self, mut node_ptr: NodePtr<K, V>)
@@ -823,6 +2687,7 @@ impl<K, V> AvlTreeMap<K, V> {
             } => {
                 node_ptr.as_mut().reset_links(parent);
                 *link_ptr.as_mut() = Some(node_ptr);
+                self.update_min_max_for_linked(node_ptr);
                 if let Some(parent_ptr) = parent {
                     self.rebalance_once(parent_ptr);
                 }
@@ -835,12 +2700,21 @@ impl<K, V> AvlTreeMap<K, V> {
                     &mut existing_node_ptr.as_mut().value,
                     &mut node_ptr.as_mut().value,
                 );
-                Node::destroy(node_ptr);
+                self.recycle_node(node_ptr);
             }
         }
     }
 
     fn unlink_node(&mut self, node_ptr: NodePtr<K, V>) {
+        // Unlinking does not change the order of the remaining nodes,
+        // so the neighbors of the first and last node take their place.
+        if self.min == Some(node_ptr) {
+            self.min = Node::successor(node_ptr);
+        }
+        if self.max == Some(node_ptr) {
+            self.max = Node::predecessor(node_ptr);
+        }
+
         unsafe {
             // Check if node to-unlink has right sub tree
             if let Some(mut min_child_ptr) = node_ptr.as_ref().right {
@@ -972,6 +2846,9 @@ impl<K, V> AvlTreeMap<K, V> {
     fn rotate_left(&mut self, mut node_ptr: NodePtr<K, V>) {
         unsafe {
             if let Some(mut right_ptr) = node_ptr.as_ref().right {
+                #[cfg(feature = "rotation_observer")]
+                self.observe_rotation(node_ptr, RotationDirection::Left);
+
                 node_ptr.as_mut().right = right_ptr.as_ref().left;
                 if let Some(mut right_left_ptr) = right_ptr.as_mut().left {
                     right_left_ptr.as_mut().parent = Some(node_ptr);
@@ -1011,6 +2888,9 @@ impl<K, V> AvlTreeMap<K, V> {
     fn rotate_right(&mut self, mut node_ptr: NodePtr<K, V>) {
         unsafe {
             if let Some(mut left_ptr) = node_ptr.as_ref().left {
+                #[cfg(feature = "rotation_observer")]
+                self.observe_rotation(node_ptr, RotationDirection::Right);
+
                 node_ptr.as_mut().left = left_ptr.as_ref().right;
                 if let Some(mut right_ptr) = left_ptr.as_ref().right {
                     right_ptr.as_mut().parent = Some(node_ptr);
@@ -1037,6 +2917,16 @@ impl<K, V> AvlTreeMap<K, V> {
         }
     }
 
+    #[cfg(feature = "rotation_observer")]
+    fn observe_rotation(&mut self, node_ptr: NodePtr<K, V>, direction: RotationDirection) {
+        if let Some(observer) = &mut self.rotation_observer {
+            observer(RotationEvent {
+                key: unsafe { &node_ptr.as_ref().key },
+                direction,
+            });
+        }
+    }
+
     /// Rebalances nodes starting from given position up to the root node.
     fn rebalance(&mut self, start_from: NodePtr<K, V>) {
         let mut current = Some(start_from);
@@ -1050,16 +2940,24 @@ impl<K, V> AvlTreeMap<K, V> {
     /// Rebalances nodes starting from given position up to the root node.
     /// Stops after first rebalance operation.
     /// This is enough to restore balance after a single insert operation.
-    fn rebalance_once(&mut self, start_from: NodePtr<K, V>) {
+    /// Returns the number of nodes whose height changed and whether a rotation occurred.
+    fn rebalance_once(&mut self, start_from: NodePtr<K, V>) -> InsertStats {
+        let mut stats = InsertStats::default();
         let mut current = Some(start_from);
         while let Some(node_ptr) = current {
-            let parent = unsafe { node_ptr.as_ref().parent };
+            let (parent, height) = unsafe { (node_ptr.as_ref().parent, node_ptr.as_ref().height) };
             let did_rebalance = self.rebalance_node(node_ptr);
             if did_rebalance {
+                // Rotation restores the height of the sub tree from before the insert
+                stats.rotated = true;
                 break;
             }
+            if unsafe { node_ptr.as_ref().height } != height {
+                stats.heights_changed += 1;
+            }
             current = parent;
         }
+        stats
     }
 
     /// Restores AVL condition (balance) at given node if necessary and adjusts height.
@@ -1095,16 +2993,291 @@ impl<K, V> AvlTreeMap<K, V> {
         }
     }
 
+    /// Returns the height of the sub tree at given link, which is 0 for an empty sub tree.
+    fn link_height(link: Link<K, V>) -> u16 {
+        match link {
+            None => 0,
+            Some(node_ptr) => unsafe { node_ptr.as_ref().height + 1 },
+        }
+    }
+
+    /// Joins two balanced trees and a middle node into a balanced tree and returns its root.
+    /// All keys of the left tree must be less than the key of the middle node and all keys of
+    /// the right tree must be greater. Takes time proportional to the difference in height.
+    unsafe fn join(
+        left: Link<K, V>,
+        mut mid_ptr: NodePtr<K, V>,
+        right: Link<K, V>,
+    ) -> NodePtr<K, V> {
+        for mut root_ptr in left.into_iter().chain(right) {
+            root_ptr.as_mut().parent = None;
+        }
+        let left_height = Self::link_height(left);
+        let right_height = Self::link_height(right);
+
+        if left_height > right_height + 1 {
+            // Hang middle node with right tree into the right spine of the taller left tree
+            //     A               A
+            //    / \             /             //       B     =>        B
+            //      / \             /             //         C               *
+            //                        /             //                       C   R
+            let mut tree = Self::with_root(left);
+            let mut parent_ptr = left.unwrap();
+            while Self::right_height(parent_ptr) > right_height + 1 {
+                parent_ptr = parent_ptr.as_ref().right.unwrap();
+            }
+            mid_ptr.as_mut().reset_links(Some(parent_ptr));
+            Self::link_children(mid_ptr, parent_ptr.as_ref().right, right);
+            parent_ptr.as_mut().right = Some(mid_ptr);
+            tree.rebalance(parent_ptr);
+            tree.root.take().unwrap()
+        } else if right_height > left_height + 1 {
+            // Hang middle node with left tree into the left spine of the taller right tree
+            let mut tree = Self::with_root(right);
+            let mut parent_ptr = right.unwrap();
+            while Self::left_height(parent_ptr) > left_height + 1 {
+                parent_ptr = parent_ptr.as_ref().left.unwrap();
+            }
+            mid_ptr.as_mut().reset_links(Some(parent_ptr));
+            Self::link_children(mid_ptr, left, parent_ptr.as_ref().left);
+            parent_ptr.as_mut().left = Some(mid_ptr);
+            tree.rebalance(parent_ptr);
+            tree.root.take().unwrap()
+        } else {
+            // Heights differ by one at most, middle node becomes the root
+            mid_ptr.as_mut().reset_links(None);
+            Self::link_children(mid_ptr, left, right);
+            mid_ptr
+        }
+    }
+
+    /// Links given sub trees as children of given node and adjusts its height.
+    unsafe fn link_children(mut node_ptr: NodePtr<K, V>, left: Link<K, V>, right: Link<K, V>) {
+        node_ptr.as_mut().left = left;
+        if let Some(mut left_ptr) = left {
+            left_ptr.as_mut().parent = Some(node_ptr);
+        }
+        node_ptr.as_mut().right = right;
+        if let Some(mut right_ptr) = right {
+            right_ptr.as_mut().parent = Some(node_ptr);
+        }
+        Self::adjust_height(node_ptr);
+    }
+
+    /// Creates a map wrapping given tree temporarily, to use the rotations on its root.
+    /// The root has to be taken out again before the map is dropped.
+    fn with_root(root: Link<K, V>) -> Self {
+        let mut map = Self::new();
+        map.root = root;
+        map
+    }
+
+    /// Creates the predicate independent part of an `ExtractIf` iterator.
+    pub(crate) fn extract_if_inner(&mut self) -> ExtractIfInner<'_, K, V> {
+        ExtractIfInner {
+            next: self.find_first(),
+            map: self,
+        }
+    }
+
+    /// Replaces the stored key equal to given key and returns it, keeping the value.
+    /// Inserts key and value if the key is not in the map.
+    pub(crate) fn replace_key(&mut self, key: K, value: V) -> Option<K>
+    where
+        K: Ord,
+    {
+        match self.find_insert_pos(&key) {
+            InsertPos::Occupied { mut node_ptr } => {
+                Some(mem::replace(unsafe { &mut node_ptr.as_mut().key }, key))
+            }
+            InsertPos::Vacant { parent, link_ptr } => {
+                unsafe {
+                    self.insert_entry_at_vacant_pos(parent, link_ptr, key, value);
+                }
+                None
+            }
+        }
+    }
+
+    /// Replaces the entries of the map with the entries of given iterator,
+    /// which must be in strictly ascending key order.
+    /// Reuses the allocated nodes of the map where possible and builds a balanced tree.
+    pub(crate) fn replace_with_sorted<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.refill_sorted(
+            iter,
+            |entry| entry,
+            |node, (key, value)| {
+                node.key = key;
+                node.value = value;
+            },
+        );
+    }
+
+    /// Replaces the entries of the map with the items of given iterator,
+    /// which must be in strictly ascending key order.
+    /// Each item is either assigned to an allocated node of the map or turned into an entry
+    /// for a newly allocated node. Builds a balanced tree.
+    /// If creating or assigning an entry panics, the map keeps the entries completed so far.
+    fn refill_sorted<T, I, C, A>(&mut self, iter: I, mut create: C, mut assign: A)
+    where
+        I: IntoIterator<Item = T>,
+        C: FnMut(T) -> (K, V),
+        A: FnMut(&mut Node<K, V>, T),
+    {
+        // Links the completed nodes and recycles the remaining ones, also when unwinding
+        struct DropGuard<'a, K, V> {
+            map: &'a mut AvlTreeMap<K, V>,
+            recycled: Vec<NodePtr<K, V>>,
+            nodes: Vec<NodePtr<K, V>>,
+        }
+
+        impl<K, V> Drop for DropGuard<'_, K, V> {
+            fn drop(&mut self) {
+                unsafe {
+                    self.map.link_sorted_nodes(&self.nodes);
+                    while let Some(node_ptr) = self.recycled.pop() {
+                        self.map.recycle_node(node_ptr);
+                    }
+                }
+            }
+        }
+
+        let mut recycled = Vec::with_capacity(self.num_nodes);
+        self.postorder(|node_ptr| recycled.push(node_ptr));
+        self.root = None;
+        self.num_nodes = 0;
+        self.min = None;
+        self.max = None;
+
+        let mut guard = DropGuard {
+            nodes: Vec::with_capacity(recycled.len()),
+            recycled,
+            map: self,
+        };
+        for item in iter {
+            // Keep the node in the recycled nodes until it is assigned completely
+            let node_ptr = match guard.recycled.last() {
+                None => {
+                    let (key, value) = create(item);
+                    guard.map.create_node(None, key, value)
+                }
+                Some(&node_ptr) => {
+                    assign(unsafe { &mut *node_ptr.as_ptr() }, item);
+                    guard.recycled.pop();
+                    node_ptr
+                }
+            };
+            guard.nodes.push(node_ptr);
+        }
+    }
+
+    /// Moves the tree into a node eater, leaving the map empty.
+    /// Unlike `NodeEater::new`, this keeps the free list of the map.
+    fn take_tree(&mut self) -> NodeEater<K, V> {
+        let node_eater = NodeEater {
+            first: self.find_first(),
+            last: self.find_last(),
+            len: self.num_nodes,
+        };
+        self.root = None;
+        self.num_nodes = 0;
+        self.min = None;
+        self.max = None;
+        node_eater
+    }
+
+    /// Removes entries with duplicate keys from given batch, which must be sorted by key,
+    /// keeping the last entry of each key.
+    fn dedup_sorted_keep_last(batch: &mut Vec<(K, V)>)
+    where
+        K: Ord,
+    {
+        batch.dedup_by(|next, prev| {
+            if next.0 == prev.0 {
+                mem::swap(next, prev);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Merges the entries of given batch, which must be in strictly ascending key order,
+    /// into the map and rebuilds a balanced tree. Entries of the batch overwrite entries
+    /// of the map with equal keys. Reuses the nodes of the map.
+    fn merge_sorted(&mut self, batch: Vec<(K, V)>)
+    where
+        K: Ord,
+    {
+        let mut nodes = Vec::with_capacity(self.num_nodes + batch.len());
+        let mut node_eater = self.take_tree();
+        let mut batch = batch.into_iter().peekable();
+        loop {
+            let ordering = match (node_eater.first, batch.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(node_ptr), Some((key, _))) => unsafe { node_ptr.as_ref().key.cmp(key) },
+            };
+            let node_ptr = match ordering {
+                Ordering::Less => node_eater.pop_first_node().unwrap(),
+                Ordering::Greater => {
+                    let (key, value) = batch.next().unwrap();
+                    self.create_node(None, key, value)
+                }
+                Ordering::Equal => {
+                    let mut node_ptr = node_eater.pop_first_node().unwrap();
+                    let (_, value) = batch.next().unwrap();
+                    unsafe {
+                        node_ptr.as_mut().value = value;
+                    }
+                    node_ptr
+                }
+            };
+            nodes.push(node_ptr);
+        }
+
+        unsafe {
+            self.link_sorted_nodes(&nodes);
+        }
+    }
+
+    /// Links given nodes, which must be in strictly ascending key order, into a balanced tree
+    /// and makes it the tree of the map. The map must be empty.
+    unsafe fn link_sorted_nodes(&mut self, nodes: &[NodePtr<K, V>]) {
+        debug_assert!(self.root.is_none());
+        self.root = Self::link_balanced(nodes, None);
+        self.num_nodes = nodes.len();
+        self.min = nodes.first().copied();
+        self.max = nodes.last().copied();
+    }
+
+    /// Links given nodes into a balanced sub tree with given parent and returns its root.
+    /// The middle node becomes the root, the nodes before and after it form its sub trees.
+    unsafe fn link_balanced(nodes: &[NodePtr<K, V>], parent: Link<K, V>) -> Link<K, V> {
+        if nodes.is_empty() {
+            return None;
+        }
+        let mid = nodes.len() / 2;
+        let mut node_ptr = nodes[mid];
+        node_ptr.as_mut().reset_links(parent);
+        node_ptr.as_mut().left = Self::link_balanced(&nodes[..mid], Some(node_ptr));
+        node_ptr.as_mut().right = Self::link_balanced(&nodes[mid + 1..], Some(node_ptr));
+        Self::adjust_height(node_ptr);
+        Some(node_ptr)
+    }
+
     /// Makes a clone of the maps tree structure.
     fn clone_tree(&self) -> Self
     where
         K: Clone,
         V: Clone,
     {
-        let mut other = Self {
-            root: None,
-            num_nodes: self.num_nodes,
-        };
+        let mut other = Self::new();
+        other.num_nodes = self.num_nodes;
 
         if let Some(mut node_ptr) = self.root {
             unsafe {
@@ -1113,6 +3286,7 @@ impl<K, V> AvlTreeMap<K, V> {
                     node_ptr.as_ref().key.clone(),
                     node_ptr.as_ref().value.clone(),
                 );
+                other_node_ptr.as_mut().height = node_ptr.as_ref().height;
                 other.root = Some(other_node_ptr);
 
                 let height = node_ptr.as_ref().height as usize;
@@ -1120,11 +3294,12 @@ impl<K, V> AvlTreeMap<K, V> {
 
                 loop {
                     if let Some(left_ptr) = node_ptr.as_ref().left {
-                        let other_left_ptr = Node::create(
+                        let mut other_left_ptr = Node::create(
                             Some(other_node_ptr),
                             left_ptr.as_ref().key.clone(),
                             left_ptr.as_ref().value.clone(),
                         );
+                        other_left_ptr.as_mut().height = left_ptr.as_ref().height;
                         other_node_ptr.as_mut().left = Some(other_left_ptr);
 
                         if node_ptr.as_ref().right.is_some() {
@@ -1145,11 +3320,12 @@ impl<K, V> AvlTreeMap<K, V> {
                     }
 
                     if let Some(right_ptr) = node_ptr.as_ref().right {
-                        let other_right_ptr = Node::create(
+                        let mut other_right_ptr = Node::create(
                             Some(other_node_ptr),
                             right_ptr.as_ref().key.clone(),
                             right_ptr.as_ref().value.clone(),
                         );
+                        other_right_ptr.as_mut().height = right_ptr.as_ref().height;
                         other_node_ptr.as_mut().right = Some(other_right_ptr);
 
                         node_ptr = right_ptr;
@@ -1163,6 +3339,8 @@ impl<K, V> AvlTreeMap<K, V> {
             }
         }
 
+        other.min = other.walk_first();
+        other.max = other.walk_last();
         other
     }
 
@@ -1245,11 +3423,16 @@ impl<K, V> AvlTreeMap<K, V> {
 
 impl<K, V> Drop for AvlTreeMap<K, V> {
     fn drop(&mut self) {
-        self.clear();
+        self.shrink_to_fit();
+        drop(NodeEater {
+            first: self.find_first(),
+            last: self.find_last(),
+            len: self.num_nodes,
+        });
     }
 }
 
-impl<K: Ord, V> Default for AvlTreeMap<K, V> {
+impl<K, V> Default for AvlTreeMap<K, V> {
     /// Creates an empty map.
     fn default() -> Self {
         Self::new()
@@ -1260,6 +3443,19 @@ impl<K: Clone, V: Clone> Clone for AvlTreeMap<K, V> {
     fn clone(&self) -> Self {
         self.clone_tree()
     }
+
+    /// Replaces the entries of the map with clones of the entries of `source`.
+    /// Reuses the allocated nodes of the map and clones into their existing keys and values.
+    fn clone_from(&mut self, source: &Self) {
+        self.refill_sorted(
+            source.iter(),
+            |(key, value)| (key.clone(), value.clone()),
+            |node, (key, value)| {
+                node.key.clone_from(key);
+                node.value.clone_from(value);
+            },
+        );
+    }
 }
 
 unsafe impl<K, V> Sync for AvlTreeMap<K, V>
@@ -1278,7 +3474,7 @@ where
 
 impl<K: PartialEq, V: PartialEq> PartialEq for AvlTreeMap<K, V> {
     fn eq(&self, other: &Self) -> bool {
-        self.len() == self.len() && self.iter().zip(other).all(|(lhs, rhs)| lhs == rhs)
+        self.len() == other.len() && self.iter().zip(other).all(|(lhs, rhs)| lhs == rhs)
     }
 }
 
@@ -1316,6 +3512,41 @@ where
     }
 }
 
+impl<K, V> fmt::Debug for DebugTree<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    /// Writes one line per node in preorder, indented by depth and annotated with the height.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_node<K: fmt::Debug, V: fmt::Debug>(
+            f: &mut fmt::Formatter,
+            link: Link<K, V>,
+            depth: usize,
+            side: &str,
+        ) -> fmt::Result {
+            if let Some(node_ptr) = link {
+                let node = unsafe { node_ptr.as_ref() };
+                writeln!(
+                    f,
+                    "{:indent$}{}{:?}: {:?} (height {})",
+                    "",
+                    side,
+                    node.key,
+                    node.value,
+                    node.height,
+                    indent = 4 * depth
+                )?;
+                fmt_node(f, node.left, depth + 1, "L: ")?;
+                fmt_node(f, node.right, depth + 1, "R: ")?;
+            }
+            Ok(())
+        }
+
+        fmt_node(f, self.map.root, 0, "")
+    }
+}
+
 impl<Q, K, V> Index<&Q> for AvlTreeMap<K, V>
 where
     K: Ord + Borrow<Q>,
@@ -1357,13 +3588,30 @@ impl<K, V> IntoIterator for AvlTreeMap<K, V> {
 }
 
 impl<K: Ord, V> Extend<(K, V)> for AvlTreeMap<K, V> {
+    /// Inserts all entries of the iterator, later entries overwrite earlier ones with equal keys.
+    ///
+    /// A batch that is large compared to the map is sorted and merged with the entries of the map
+    /// into a balanced tree. A small batch, or one of unknown size, is inserted entry by entry
+    /// without buffering.
     fn extend<I>(&mut self, iter: I)
     where
         I: IntoIterator<Item = (K, V)>,
     {
-        iter.into_iter().for_each(move |(key, value)| {
-            self.insert(key, value);
-        });
+        let iter = iter.into_iter();
+        // Merging touches all entries of the map, inserting touches O(log n) nodes per entry
+        let (min_len, _) = iter.size_hint();
+        if min_len < self.num_nodes / 16 {
+            for (key, value) in iter {
+                self.insert(key, value);
+            }
+            return;
+        }
+
+        let mut batch: Vec<(K, V)> = iter.collect();
+        // Stable sort keeps equal keys in order, so the last one can be kept
+        batch.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        Self::dedup_sorted_keep_last(&mut batch);
+        self.merge_sorted(batch);
     }
 }
 
@@ -1388,6 +3636,73 @@ impl<K: Hash, V: Hash> Hash for AvlTreeMap<K, V> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a, K: Ord + arbitrary::Arbitrary<'a>, V: arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a>
+    for AvlTreeMap<K, V>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let entries: Vec<(K, V)> = u.arbitrary()?;
+        Ok(entries.into_iter().collect())
+    }
+
+    fn arbitrary_take_rest(u: arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let entries: Vec<(K, V)> = arbitrary::Arbitrary::arbitrary_take_rest(u)?;
+        Ok(entries.into_iter().collect())
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <Vec<(K, V)> as arbitrary::Arbitrary>::size_hint(depth)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K: Sync, V: Sync> IntoParallelIterator for &'a AvlTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type Iter = ParIter<'a, K, V>;
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter {
+            node_iter: unsafe { NodeIter::new(self.find_first(), self.find_last()) },
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: Ord + Send, V: Send> ParallelExtend<(K, V)> for AvlTreeMap<K, V> {
+    /// Inserts all entries of the parallel iterator, later entries overwrite earlier ones
+    /// with equal keys.
+    ///
+    /// The entries are collected and sorted in parallel, then merged with the entries of the map
+    /// into a balanced tree like `extend` does.
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let mut batch: Vec<(K, V)> = par_iter.into_par_iter().collect();
+        if batch.len() < self.num_nodes / 16 {
+            for (key, value) in batch {
+                self.insert(key, value);
+            }
+            return;
+        }
+
+        batch.par_sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        Self::dedup_sorted_keep_last(&mut batch);
+        self.merge_sorted(batch);
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: Ord + Send, V: Send> FromParallelIterator<(K, V)> for AvlTreeMap<K, V> {
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new();
+        map.par_extend(par_iter);
+        map
+    }
+}
+
 // endregion Trait of AvlTreeMap
 
 // region Implementation of Node
@@ -1409,6 +3724,87 @@ impl<K, V> Node<K, V> {
         (boxed.key, boxed.value)
     }
 
+    /// Returns the in-order successor of given node, or None if it is the last node of the tree.
+    fn successor(node_ptr: NodePtr<K, V>) -> Link<K, V> {
+        unsafe {
+            if let Some(mut next_ptr) = node_ptr.as_ref().right {
+                // Successor is smallest child in right sub tree
+                while let Some(left_ptr) = next_ptr.as_ref().left {
+                    next_ptr = left_ptr;
+                }
+                return Some(next_ptr);
+            }
+            // Successor is the first parent which is reached from a left child
+            let mut current = node_ptr;
+            while let Some(parent_ptr) = current.as_ref().parent {
+                if parent_ptr.as_ref().left == Some(current) {
+                    return Some(parent_ptr);
+                }
+                current = parent_ptr;
+            }
+            None
+        }
+    }
+
+    /// Returns the in-order predecessor of given node, or None if it is the first node of the tree.
+    fn predecessor(node_ptr: NodePtr<K, V>) -> Link<K, V> {
+        unsafe {
+            if let Some(mut prev_ptr) = node_ptr.as_ref().left {
+                // Predecessor is biggest child in left sub tree
+                while let Some(right_ptr) = prev_ptr.as_ref().right {
+                    prev_ptr = right_ptr;
+                }
+                return Some(prev_ptr);
+            }
+            // Predecessor is the first parent which is reached from a right child
+            let mut current = node_ptr;
+            while let Some(parent_ptr) = current.as_ref().parent {
+                if parent_ptr.as_ref().right == Some(current) {
+                    return Some(parent_ptr);
+                }
+                current = parent_ptr;
+            }
+            None
+        }
+    }
+
+    /// Returns the lowest common ancestor of two nodes of the same tree,
+    /// i.e. the root of the smallest sub tree containing both nodes.
+    #[cfg(feature = "rayon")]
+    fn common_ancestor(lhs_ptr: NodePtr<K, V>, rhs_ptr: NodePtr<K, V>) -> NodePtr<K, V> {
+        let mut lhs_ptr = lhs_ptr;
+        let mut rhs_ptr = rhs_ptr;
+        let mut lhs_depth = Node::depth(lhs_ptr);
+        let mut rhs_depth = Node::depth(rhs_ptr);
+        unsafe {
+            // Walk up to the same depth, then walk up in lockstep until the paths meet
+            while lhs_depth > rhs_depth {
+                lhs_ptr = lhs_ptr.as_ref().parent.unwrap();
+                lhs_depth -= 1;
+            }
+            while rhs_depth > lhs_depth {
+                rhs_ptr = rhs_ptr.as_ref().parent.unwrap();
+                rhs_depth -= 1;
+            }
+            while lhs_ptr != rhs_ptr {
+                lhs_ptr = lhs_ptr.as_ref().parent.unwrap();
+                rhs_ptr = rhs_ptr.as_ref().parent.unwrap();
+            }
+        }
+        lhs_ptr
+    }
+
+    /// Returns the number of links from the root of the tree to given node.
+    fn depth(node_ptr: NodePtr<K, V>) -> usize {
+        let mut depth = 0;
+        let mut current = node_ptr;
+        while let Some(parent_ptr) = unsafe { current.as_ref().parent } {
+            depth += 1;
+            current = parent_ptr;
+        }
+        depth
+    }
+
     fn reset_links(&mut self, parent: Link<K, V>) {
         self.parent = parent;
         self.left = None;
@@ -1418,6 +3814,23 @@ impl<K, V> Node<K, V> {
 }
 // endregion Implementation of Node
 
+// region Implementation of RangeError
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeError::StartGreaterThanEnd => write!(f, "range start is greater than range end"),
+            RangeError::EmptyExcludedExcluded => {
+                write!(f, "range start and end are equal and excluded")
+            }
+        }
+    }
+}
+
+impl core::error::Error for RangeError {}
+
+// endregion Implementation of RangeError
+
 // region Implementation of entries
 
 impl<'a, K, V> Entry<'a, K, V> {
@@ -1436,23 +3849,59 @@ impl<'a, K, V> Entry<'a, K, V> {
                 f(o.get_mut());
                 Entry::Occupied(o)
             }
-            Entry::Vacant(v) => Entry::Vacant(v),
+            Entry::Vacant(v) => Entry::Vacant(v),
+        }
+    }
+
+    /// Inserts value into the map if the entry is vacant.
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(value),
+        }
+    }
+
+    /// Calls provided closure and inserts result value into the map if the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, create_value: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(create_value()),
+        }
+    }
+
+    /// Calls provided closure with the key and inserts result value into the map
+    /// if the entry is vacant.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, create_value: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                let value = create_value(v.key());
+                v.insert(value)
+            }
         }
     }
 
-    /// Inserts value into the map if the entry is vacant.
-    pub fn or_insert(self, value: V) -> &'a mut V {
+    /// Removes the entry from the map and returns its value if the entry is occupied
+    /// and the predicate returns true for its value. Leaves the map unchanged otherwise.
+    pub fn remove_if<F: FnOnce(&mut V) -> bool>(self, pred: F) -> Option<V> {
         match self {
-            Entry::Occupied(o) => o.into_mut(),
-            Entry::Vacant(v) => v.insert(value),
+            Entry::Occupied(mut o) => {
+                if pred(o.get_mut()) {
+                    Some(o.remove())
+                } else {
+                    None
+                }
+            }
+            Entry::Vacant(_) => None,
         }
     }
 
     /// Calls provided closure and inserts result value into the map if the entry is vacant.
-    pub fn or_insert_with<F: FnOnce() -> V>(self, create_value: F) -> &'a mut V {
+    /// Returns references to the stored key and the value of the entry.
+    pub fn or_insert_with_kv<F: FnOnce() -> V>(self, create_value: F) -> (&'a K, &'a mut V) {
         match self {
-            Entry::Occupied(o) => o.into_mut(),
-            Entry::Vacant(v) => v.insert(create_value()),
+            Entry::Occupied(o) => o.into_key_value(),
+            Entry::Vacant(v) => v.insert_entry(create_value()).into_key_value(),
         }
     }
 }
@@ -1490,15 +3939,30 @@ impl<'a, K, V> VacantEntry<'a, K, V> {
     /// Inserts the value into the map for the entry. Returns a mutable reference to the value.
     pub fn insert(self, value: V) -> &'a mut V {
         unsafe {
+            let (node_ptr, _) =
+                self.map
+                    .insert_entry_at_vacant_pos(self.parent, self.insert_pos, self.key, value);
+            &mut (*node_ptr.as_ptr()).value
+        }
+    }
+
+    /// Inserts the value into the map for the entry. Returns the occupied entry of the value.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V> {
+        let (node_ptr, _) = unsafe {
             self.map
                 .insert_entry_at_vacant_pos(self.parent, self.insert_pos, self.key, value)
+        };
+        OccupiedEntry {
+            map: self.map,
+            node_ptr,
+            marker: PhantomData,
         }
     }
 }
 
-impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for VacantEntry<'_, K, V> {
+impl<K: fmt::Debug, V> fmt::Debug for VacantEntry<'_, K, V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("OccupiedEntry")
+        f.debug_struct("VacantEntry")
             .field("key", self.key())
             .finish()
     }
@@ -1529,6 +3993,12 @@ impl<'a, K, V> OccupiedEntry<'a, K, V> {
         unsafe { &mut (*self.node_ptr.as_ptr()).value }
     }
 
+    /// Converts the entry into references to its key and mutable value.
+    fn into_key_value(self) -> (&'a K, &'a mut V) {
+        let node = unsafe { &mut *self.node_ptr.as_ptr() };
+        (&node.key, &mut node.value)
+    }
+
     /// Inserts the value into the map entry and returns its old value.
     pub fn insert(&mut self, value: V) -> V {
         unsafe { self.map.insert_value_at_occupied_pos(self.node_ptr, value) }
@@ -1543,9 +4013,44 @@ impl<'a, K, V> OccupiedEntry<'a, K, V> {
     pub fn remove_entry(self) -> (K, V) {
         unsafe { self.map.remove_entry_at_occupied_pos(self.node_ptr) }
     }
+
+    /// Removes the entry from the map and returns its key and value if the predicate holds.
+    /// Otherwise, the entry is handed back unchanged.
+    pub fn remove_entry_if<F>(self, pred: F) -> Result<(K, V), Self>
+    where
+        F: FnOnce(&K, &V) -> bool,
+    {
+        if pred(self.key(), self.get()) {
+            Ok(self.remove_entry())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Converts the entry into the entry of the next key in the map.
+    /// Returns None if the entry has the last key.
+    pub fn next_entry(self) -> Option<OccupiedEntry<'a, K, V>> {
+        let node_ptr = Node::successor(self.node_ptr)?;
+        Some(OccupiedEntry {
+            map: self.map,
+            node_ptr,
+            marker: PhantomData,
+        })
+    }
+
+    /// Converts the entry into the entry of the previous key in the map.
+    /// Returns None if the entry has the first key.
+    pub fn prev_entry(self) -> Option<OccupiedEntry<'a, K, V>> {
+        let node_ptr = Node::predecessor(self.node_ptr)?;
+        Some(OccupiedEntry {
+            map: self.map,
+            node_ptr,
+            marker: PhantomData,
+        })
+    }
 }
 
-impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for OccupiedEntry<'_, K, V> {
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OccupiedEntry<'_, K, V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("OccupiedEntry")
             .field("key", self.key())
@@ -1558,6 +4063,94 @@ unsafe impl<K, V> Send for OccupiedEntry<'_, K, V> {}
 
 unsafe impl<K, V> Sync for OccupiedEntry<'_, K, V> {}
 
+impl<'a, K, V> RawEntryBuilderMut<'a, K, V> {
+    /// Looks up the map entry of given key.
+    pub fn from_key<Q>(self, key: &Q) -> RawEntryMut<'a, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.map.find_insert_pos(key) {
+            InsertPos::Vacant { parent, link_ptr } => RawEntryMut::Vacant(RawVacantEntryMut {
+                map: self.map,
+                parent,
+                insert_pos: link_ptr,
+                marker: PhantomData,
+            }),
+            InsertPos::Occupied { node_ptr } => RawEntryMut::Occupied(OccupiedEntry {
+                map: self.map,
+                node_ptr,
+                marker: PhantomData,
+            }),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for RawEntryBuilderMut<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawEntryBuilderMut").finish()
+    }
+}
+
+impl<'a, K, V> RawEntryMut<'a, K, V> {
+    /// Inserts key and value into the map if the entry is vacant.
+    /// Returns references to the key and mutable value of the entry.
+    pub fn or_insert(self, key: K, value: V) -> (&'a K, &'a mut V) {
+        match self {
+            RawEntryMut::Occupied(o) => o.into_key_value(),
+            RawEntryMut::Vacant(v) => v.insert(key, value),
+        }
+    }
+
+    /// Calls provided closure and inserts resulting key and value into the map
+    /// if the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> (K, V)>(self, create: F) -> (&'a K, &'a mut V) {
+        match self {
+            RawEntryMut::Occupied(o) => o.into_key_value(),
+            RawEntryMut::Vacant(v) => {
+                let (key, value) = create();
+                v.insert(key, value)
+            }
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for RawEntryMut<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RawEntryMut::Vacant(ref v) => f.debug_tuple("RawEntryMut").field(v).finish(),
+            RawEntryMut::Occupied(ref o) => f.debug_tuple("RawEntryMut").field(o).finish(),
+        }
+    }
+}
+
+impl<'a, K, V> RawVacantEntryMut<'a, K, V> {
+    /// Inserts key and value into the map for the entry.
+    /// Returns references to the inserted key and mutable value.
+    ///
+    /// The key must be equal to the key the entry has been looked up with.
+    /// Otherwise the order of the map is broken.
+    pub fn insert(self, key: K, value: V) -> (&'a K, &'a mut V) {
+        unsafe {
+            let (node_ptr, _) =
+                self.map
+                    .insert_entry_at_vacant_pos(self.parent, self.insert_pos, key, value);
+            let node = &mut *node_ptr.as_ptr();
+            (&node.key, &mut node.value)
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for RawVacantEntryMut<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawVacantEntryMut").finish()
+    }
+}
+
+unsafe impl<K, V> Send for RawVacantEntryMut<'_, K, V> {}
+
+unsafe impl<K, V> Sync for RawVacantEntryMut<'_, K, V> {}
+
 // endregion Implementation of entries
 
 // region Implementation of iterators
@@ -1572,6 +4165,12 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
             Some((key, value))
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
+
+    sorted_iterator_methods!();
 }
 
 impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
@@ -1587,7 +4186,8 @@ impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
 
 impl<'a, K, V> Iter<'a, K, V> {
     /// Peeks at next value without advancing the iterator.
-    pub(crate) fn peek(&self) -> Option<<Self as Iterator>::Item> {
+    /// Returns the same item a subsequent call to `next` would return.
+    pub fn peek(&self) -> Option<<Self as Iterator>::Item> {
         let node_ptr = self.node_iter.peek_first()?;
         unsafe {
             let key: &'a K = &(*node_ptr.as_ptr()).key;
@@ -1595,12 +4195,43 @@ impl<'a, K, V> Iter<'a, K, V> {
             Some((key, value))
         }
     }
+
+    /// Peeks at next value from the back without advancing the iterator.
+    /// Returns the same item a subsequent call to `next_back` would return.
+    pub fn peek_back(&self) -> Option<<Self as Iterator>::Item> {
+        let node_ptr = self.node_iter.peek_last()?;
+        unsafe {
+            let key: &'a K = &(*node_ptr.as_ptr()).key;
+            let value: &'a V = &(*node_ptr.as_ptr()).value;
+            Some((key, value))
+        }
+    }
+
+    /// Returns the key of the next element, from which the iteration can be resumed
+    /// later with `range_from(Bound::Included(key))`, even if the map has been modified.
+    pub fn checkpoint(&self) -> Option<&'a K> {
+        let node_ptr = self.node_iter.peek_first()?;
+        Some(unsafe { &(*node_ptr.as_ptr()).key })
+    }
+
+    /// Advances the iterator to the first remaining element with a key not less than given key,
+    /// in O(log n) time. Does nothing if the next element already has such a key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn seek_forward<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.node_iter.seek_first(key);
+    }
 }
 
 impl<K, V> Clone for Iter<'_, K, V> {
     fn clone(&self) -> Self {
         Self {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
+            node_iter: unsafe { self.node_iter.copy() },
         }
     }
 }
@@ -1625,12 +4256,177 @@ impl<K: fmt::Debug, V> Iter<'_, K, V> {
     /// Shows only the keys of the iterator, used by set implementation.
     pub(crate) fn fmt_keys(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let keys = Keys {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
+            node_iter: unsafe { self.node_iter.copy() },
         };
         write!(f, "{:?}", keys)
     }
 }
 
+impl<'a, K, V> Iterator for IterIndexed<'a, K, V> {
+    type Item = (usize, &'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.iter.next()?;
+        let index = self.front;
+        self.front += 1;
+        Some((index, key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterIndexed<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.iter.next_back()?;
+        self.back -= 1;
+        Some((self.back, key, value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterIndexed<'_, K, V> {}
+
+impl<K, V> Clone for IterIndexed<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+            front: self.front,
+            back: self.back,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for IterIndexed<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        let mut sep = "";
+        for (index, key, value) in self.clone() {
+            write!(f, "{}({:?}, {:?}, {:?})", sep, index, key, value)?;
+            sep = ", ";
+        }
+        write!(f, "]")
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for MergeJoin<'a, K, V> {
+    type Item = (&'a K, MergeSide<'a, V>);
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.lhs_iter.peek(), self.rhs_iter.peek()) {
+            (None, None) => None,
+            (Some((key, value)), None) => {
+                self.lhs_iter.next();
+                Some((key, MergeSide::Left(value)))
+            }
+            (None, Some((key, value))) => {
+                self.rhs_iter.next();
+                Some((key, MergeSide::Right(value)))
+            }
+            (Some((lhs_key, lhs_value)), Some((rhs_key, rhs_value))) => {
+                match lhs_key.cmp(rhs_key) {
+                    Ordering::Less => {
+                        self.lhs_iter.next();
+                        Some((lhs_key, MergeSide::Left(lhs_value)))
+                    }
+                    Ordering::Equal => {
+                        self.lhs_iter.next();
+                        self.rhs_iter.next();
+                        Some((lhs_key, MergeSide::Both(lhs_value, rhs_value)))
+                    }
+                    Ordering::Greater => {
+                        self.rhs_iter.next();
+                        Some((rhs_key, MergeSide::Right(rhs_value)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for MergeJoin<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match (self.lhs_iter.peek_back(), self.rhs_iter.peek_back()) {
+            (None, None) => None,
+            (Some((key, value)), None) => {
+                self.lhs_iter.next_back();
+                Some((key, MergeSide::Left(value)))
+            }
+            (None, Some((key, value))) => {
+                self.rhs_iter.next_back();
+                Some((key, MergeSide::Right(value)))
+            }
+            (Some((lhs_key, lhs_value)), Some((rhs_key, rhs_value))) => {
+                match lhs_key.cmp(rhs_key) {
+                    Ordering::Greater => {
+                        self.lhs_iter.next_back();
+                        Some((lhs_key, MergeSide::Left(lhs_value)))
+                    }
+                    Ordering::Equal => {
+                        self.lhs_iter.next_back();
+                        self.rhs_iter.next_back();
+                        Some((lhs_key, MergeSide::Both(lhs_value, rhs_value)))
+                    }
+                    Ordering::Less => {
+                        self.rhs_iter.next_back();
+                        Some((rhs_key, MergeSide::Right(rhs_value)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, Q, F> Iterator for GroupBy<'a, K, V, F>
+where
+    Q: PartialEq,
+    F: FnMut(&K) -> Q,
+{
+    type Item = (Q, Vec<(&'a K, &'a V)>);
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.iter.next()?;
+        let group_key = (self.proj)(first.0);
+        let mut group = Vec::new();
+        group.push(first);
+        while let Some(entry) = self.iter.peek() {
+            if (self.proj)(entry.0) != group_key {
+                break;
+            }
+            group.push(entry);
+            self.iter.next();
+        }
+        Some((group_key, group))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, F> fmt::Debug for GroupBy<'_, K, V, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GroupBy").field("iter", &self.iter).finish()
+    }
+}
+
+impl<K, V> Clone for MergeJoin<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            lhs_iter: self.lhs_iter.clone(),
+            rhs_iter: self.rhs_iter.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for MergeJoin<'_, K, V>
+where
+    K: fmt::Debug + Ord,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
 impl<'a, K, V> Iterator for Range<'a, K, V> {
     type Item = (&'a K, &'a V);
     fn next(&mut self) -> Option<Self::Item> {
@@ -1641,6 +4437,12 @@ impl<'a, K, V> Iterator for Range<'a, K, V> {
             Some((key, value))
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
+
+    sorted_iterator_methods!();
 }
 
 impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
@@ -1656,7 +4458,8 @@ impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
 
 impl<'a, K, V> Range<'a, K, V> {
     /// Peeks at next value without advancing the iterator.
-    pub(crate) fn peek(&self) -> Option<<Self as Iterator>::Item> {
+    /// Returns the same item a subsequent call to `next` would return.
+    pub fn peek(&self) -> Option<<Self as Iterator>::Item> {
         let node_ptr = self.node_iter.peek_first()?;
         unsafe {
             let key: &'a K = &(*node_ptr.as_ptr()).key;
@@ -1664,12 +4467,43 @@ impl<'a, K, V> Range<'a, K, V> {
             Some((key, value))
         }
     }
+
+    /// Peeks at next value from the back without advancing the iterator.
+    /// Returns the same item a subsequent call to `next_back` would return.
+    pub fn peek_back(&self) -> Option<<Self as Iterator>::Item> {
+        let node_ptr = self.node_iter.peek_last()?;
+        unsafe {
+            let key: &'a K = &(*node_ptr.as_ptr()).key;
+            let value: &'a V = &(*node_ptr.as_ptr()).value;
+            Some((key, value))
+        }
+    }
+
+    /// Returns the key of the next element, from which the iteration can be resumed
+    /// later with `range_from(Bound::Included(key))`, even if the map has been modified.
+    pub fn checkpoint(&self) -> Option<&'a K> {
+        let node_ptr = self.node_iter.peek_first()?;
+        Some(unsafe { &(*node_ptr.as_ptr()).key })
+    }
+
+    /// Advances the iterator to the first remaining element with a key not less than given key,
+    /// in O(log n) time. Does nothing if the next element already has such a key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    pub fn seek_forward<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.node_iter.seek_first(key);
+    }
 }
 
 impl<K, V> Clone for Range<'_, K, V> {
     fn clone(&self) -> Self {
         Self {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
+            node_iter: unsafe { self.node_iter.copy() },
         }
     }
 }
@@ -1699,83 +4533,148 @@ impl<'a, K, V> Iterator for Keys<'a, K, V> {
             Some(key)
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
+
+    sorted_iterator_methods!();
 }
 
 impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         let node_ptr = self.node_iter.pop_last()?;
         unsafe {
-            let key: &'a K = &(*node_ptr.as_ptr()).key;
-            Some(key)
+            let key: &'a K = &(*node_ptr.as_ptr()).key;
+            Some(key)
+        }
+    }
+}
+
+impl<K, V> Clone for Keys<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            node_iter: unsafe { self.node_iter.copy() },
+        }
+    }
+}
+
+impl<K: fmt::Debug, V> fmt::Debug for Keys<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        let mut sep = "";
+        for key in self.clone() {
+            write!(f, "{}{:?}", sep, key)?;
+            sep = ", ";
+        }
+        write!(f, "]")
+    }
+}
+
+impl<'a, K: fmt::Debug, V> Range<'a, K, V> {
+    /// Shows only the keys of the iterator, used by set implementation.
+    pub(crate) fn fmt_keys(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = Keys {
+            node_iter: unsafe { self.node_iter.copy() },
+        };
+        write!(f, "{:?}", keys)
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_ptr = self.node_iter.pop_first()?;
+        unsafe {
+            let value: &'a V = &(*node_ptr.as_ptr()).value;
+            Some(value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node_ptr = self.node_iter.pop_last()?;
+        unsafe {
+            let value: &'a V = &(*node_ptr.as_ptr()).value;
+            Some(value)
         }
     }
 }
 
-impl<K, V> Clone for Keys<'_, K, V> {
+impl<K, V> Clone for Values<'_, K, V> {
     fn clone(&self) -> Self {
         Self {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
+            node_iter: unsafe { self.node_iter.copy() },
         }
     }
 }
 
-impl<K: fmt::Debug, V> fmt::Debug for Keys<'_, K, V> {
+impl<K, V: fmt::Debug> fmt::Debug for Values<'_, K, V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[")?;
         let mut sep = "";
-        for key in self.clone() {
-            write!(f, "{}{:?}", sep, key)?;
+        for value in self.clone() {
+            write!(f, "{}{:?}", sep, value)?;
             sep = ", ";
         }
         write!(f, "]")
     }
 }
 
-impl<'a, K: fmt::Debug, V> Range<'a, K, V> {
-    /// Shows only the keys of the iterator, used by set implementation.
-    pub(crate) fn fmt_keys(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let keys = Keys {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
-        };
-        write!(f, "{:?}", keys)
-    }
-}
-
-impl<'a, K, V> Iterator for Values<'a, K, V> {
-    type Item = &'a V;
+impl<'a, K, V> Iterator for IterWithDepth<'a, K, V> {
+    type Item = (usize, &'a K, &'a V);
     fn next(&mut self) -> Option<Self::Item> {
         let node_ptr = self.node_iter.pop_first()?;
         unsafe {
+            let key: &'a K = &(*node_ptr.as_ptr()).key;
             let value: &'a V = &(*node_ptr.as_ptr()).value;
-            Some(value)
+            Some((Node::depth(node_ptr), key, value))
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
 }
 
-impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+impl<'a, K, V> DoubleEndedIterator for IterWithDepth<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         let node_ptr = self.node_iter.pop_last()?;
         unsafe {
+            let key: &'a K = &(*node_ptr.as_ptr()).key;
             let value: &'a V = &(*node_ptr.as_ptr()).value;
-            Some(value)
+            Some((Node::depth(node_ptr), key, value))
         }
     }
 }
 
-impl<K, V> Clone for Values<'_, K, V> {
+impl<K, V> Clone for IterWithDepth<'_, K, V> {
     fn clone(&self) -> Self {
         Self {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
+            node_iter: unsafe { self.node_iter.copy() },
         }
     }
 }
 
-impl<K, V: fmt::Debug> fmt::Debug for Values<'_, K, V> {
+impl<K, V> fmt::Debug for IterWithDepth<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[")?;
         let mut sep = "";
-        for value in self.clone() {
-            write!(f, "{}{:?}", sep, value)?;
+        for (depth, key, value) in self.clone() {
+            write!(f, "{}({}, {:?}, {:?})", sep, depth, key, value)?;
             sep = ", ";
         }
         write!(f, "]")
@@ -1792,6 +4691,10 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
             Some((key, value))
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
 }
 
 impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
@@ -1805,6 +4708,16 @@ impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
     }
 }
 
+impl<K, V> IterMut<'_, K, V> {
+    /// Gets a shared iterator over the remaining entries, without advancing this iterator.
+    pub fn reborrow(&self) -> Iter<'_, K, V> {
+        // Safe to access elements in remaining range, no mutable references have been created yet
+        Iter {
+            node_iter: unsafe { self.node_iter.copy() },
+        }
+    }
+}
+
 impl<K, V> fmt::Debug for IterMut<'_, K, V>
 where
     K: fmt::Debug,
@@ -1813,11 +4726,7 @@ where
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[")?;
         let mut sep = "";
-        // Safe to access elements in remaining range, no mutable references have been created yet
-        let iter = Iter {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
-        };
-        for (key, value) in iter {
+        for (key, value) in self.reborrow() {
             write!(f, "{}({:?}, {:?})", sep, key, value)?;
             sep = ", ";
         }
@@ -1835,6 +4744,10 @@ impl<'a, K, V> Iterator for RangeMut<'a, K, V> {
             Some((key, value))
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
 }
 
 impl<'a, K, V> DoubleEndedIterator for RangeMut<'a, K, V> {
@@ -1848,6 +4761,16 @@ impl<'a, K, V> DoubleEndedIterator for RangeMut<'a, K, V> {
     }
 }
 
+impl<K, V> RangeMut<'_, K, V> {
+    /// Gets a shared iterator over the remaining entries, without advancing this iterator.
+    pub fn reborrow(&self) -> Iter<'_, K, V> {
+        // Safe to access elements in remaining range, no mutable references have been created yet
+        Iter {
+            node_iter: unsafe { self.node_iter.copy() },
+        }
+    }
+}
+
 impl<K, V> fmt::Debug for RangeMut<'_, K, V>
 where
     K: fmt::Debug,
@@ -1856,11 +4779,7 @@ where
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[")?;
         let mut sep = "";
-        // Safe to access elements in remaining range, no mutable references have been created yet
-        let iter = Iter {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
-        };
-        for (key, value) in iter {
+        for (key, value) in self.reborrow() {
             write!(f, "{}({:?}, {:?})", sep, key, value)?;
             sep = ", ";
         }
@@ -1877,6 +4796,10 @@ impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
             Some(value)
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_iter.size_hint()
+    }
 }
 
 impl<'a, K, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
@@ -1889,15 +4812,21 @@ impl<'a, K, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
     }
 }
 
+impl<K, V> ValuesMut<'_, K, V> {
+    /// Gets a shared iterator over the remaining values, without advancing this iterator.
+    pub fn reborrow(&self) -> Values<'_, K, V> {
+        // Safe to access elements in remaining range, no mutable references have been created yet
+        Values {
+            node_iter: unsafe { self.node_iter.copy() },
+        }
+    }
+}
+
 impl<K, V: fmt::Debug> fmt::Debug for ValuesMut<'_, K, V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[")?;
         let mut sep = "";
-        // Safe to access elements in remaining range, no mutable references have been created yet
-        let values = Values {
-            node_iter: unsafe { NodeIter::new(self.node_iter.first, self.node_iter.last) },
-        };
-        for value in values {
+        for value in self.reborrow() {
             write!(f, "{}{:?}", sep, value)?;
             sep = ", "
         }
@@ -1941,6 +4870,10 @@ impl<K, V> Iterator for IntoIter<K, V> {
     fn next(&mut self) -> Option<Self::Item> {
         self.node_eater.pop_first()
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_eater.size_hint()
+    }
 }
 
 impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
@@ -1949,6 +4882,200 @@ impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
     }
 }
 
+impl<K, V, F> Iterator for ExtractIf<'_, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next(&mut self.pred)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, F> fmt::Debug for ExtractIf<'_, K, V, F>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.inner.remaining())
+    }
+}
+
+impl<K, V> ExtractIfInner<'_, K, V> {
+    /// Removes and returns the next entry matching the predicate.
+    pub(crate) fn next<F>(&mut self, pred: &mut F) -> Option<(K, V)>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        while let Some(node_ptr) = self.next {
+            unsafe {
+                self.next = Node::successor(node_ptr);
+                let node = &mut *node_ptr.as_ptr();
+                if pred(&node.key, &mut node.value) {
+                    return Some(self.map.remove_entry_at_occupied_pos(node_ptr));
+                }
+            }
+        }
+        None
+    }
+
+    pub(crate) fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.num_nodes))
+    }
+
+    /// Returns an iterator over the entries not visited yet.
+    pub(crate) fn remaining(&self) -> Iter<'_, K, V> {
+        let last = self.next.and(self.map.find_last());
+        Iter {
+            node_iter: unsafe { NodeIter::new(self.next, last) },
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Drain<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Safe to access elements in remaining range, no mutable references have been created yet
+        let iter = Iter {
+            node_iter: unsafe { NodeIter::new(self.node_eater.first, self.node_eater.last) },
+        };
+        write!(f, "{:?}", iter)
+    }
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_ptr = self.node_eater.pop_first_node()?;
+        Some(unsafe { self.map.recycle_node(node_ptr) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_eater.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Drain<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node_ptr = self.node_eater.pop_last_node()?;
+        Some(unsafe { self.map.recycle_node(node_ptr) })
+    }
+}
+
+impl<K, V> Drop for Drain<'_, K, V> {
+    /// Drops the elements not consumed yet.
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<K: fmt::Debug, V> Drain<'_, K, V> {
+    /// Shows only the keys of the iterator, used by set implementation.
+    pub(crate) fn fmt_keys(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = Keys {
+            node_iter: unsafe { NodeIter::new(self.node_eater.first, self.node_eater.last) },
+        };
+        write!(f, "{:?}", keys)
+    }
+}
+
+impl<K, V> fmt::Debug for IntoRange<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Safe to access elements in remaining range, no mutable references have been created yet
+        let iter = Iter {
+            node_iter: unsafe { NodeIter::new(self.node_eater.first, self.node_eater.last) },
+        };
+        write!(f, "{:?}", iter)
+    }
+}
+
+impl<K, V> Iterator for IntoRange<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node_eater.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_eater.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoRange<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.node_eater.pop_last()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K: Sync, V: Sync> ParallelIterator for ParIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let producer = NodeProducer {
+            node_iter: self.node_iter,
+        };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: fmt::Debug, V> ParIter<'_, K, V> {
+    /// Shows only the keys of the iterator, used by set implementation.
+    pub(crate) fn fmt_keys(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = Keys {
+            node_iter: unsafe { self.node_iter.copy() },
+        };
+        write!(f, "{:?}", keys)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for ParIter<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let iter = Iter {
+            node_iter: unsafe { self.node_iter.copy() },
+        };
+        write!(f, "{:?}", iter)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K: Sync, V: Sync> UnindexedProducer for NodeProducer<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn split(self) -> (Self, Option<Self>) {
+        let (node_iter, split_off) = self.node_iter.split();
+        (
+            Self { node_iter },
+            split_off.map(|node_iter| Self { node_iter }),
+        )
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        folder.consume_iter(Iter {
+            node_iter: self.node_iter,
+        })
+    }
+}
+
 // endregion Implementation of iterators
 
 // region Implementation of NodeIter
@@ -1958,19 +5085,55 @@ impl<'a, K, V> NodeIter<'a, K, V> {
         NodeIter {
             first,
             last,
+            len: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a range of given number of nodes, e.g. over all nodes of a map.
+    unsafe fn with_len(first: Link<K, V>, last: Link<K, V>, len: usize) -> Self {
+        NodeIter {
+            len: Some(len),
+            ..NodeIter::new(first, last)
+        }
+    }
+
+    /// Creates a copy of the range with an unbounded lifetime.
+    unsafe fn copy<'b>(&self) -> NodeIter<'b, K, V> {
+        NodeIter {
+            first: self.first,
+            last: self.last,
+            len: self.len,
             marker: PhantomData,
         }
     }
 
+    /// Returns the bounds on the remaining length of the range.
+    /// The length is exact if it is known, e.g. for the range of all nodes of a map.
+    /// Otherwise a non-empty range has at least one node, an empty range yields nothing.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (self.first, self.len) {
+            (Some(_), Some(len)) => (len, Some(len)),
+            (Some(_), None) => (1, None),
+            (None, _) => (0, Some(0)),
+        }
+    }
+
     /// Peeks at first node without taking it of the range.
     fn peek_first(&self) -> Link<K, V> {
         self.first
     }
 
+    /// Peeks at last node without taking it of the range.
+    fn peek_last(&self) -> Link<K, V> {
+        self.last
+    }
+
     /// Pops first node from the range or returns None if range is empty.
     fn pop_first(&mut self) -> Link<K, V> {
         let first = self.first;
         let node_ptr = first?;
+        self.len = self.len.map(|len| len - 1);
         if self.first == self.last {
             // Last remaining node in the range -> end iteration
             self.first = None;
@@ -2008,6 +5171,7 @@ impl<'a, K, V> NodeIter<'a, K, V> {
     fn pop_last(&mut self) -> Link<K, V> {
         let last = self.last;
         let node_ptr = last?;
+        self.len = self.len.map(|len| len - 1);
         if self.last == self.first {
             // Last remaining node in the range -> end iteration
             self.first = None;
@@ -2041,6 +5205,81 @@ impl<'a, K, V> NodeIter<'a, K, V> {
         }
         last
     }
+
+    /// Advances the start of the range to the first node with a key not less than given key.
+    /// Climbs up from the first node to an ancestor with a greater key, then searches its
+    /// left sub tree, which takes O(log n) time.
+    fn seek_first<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (Some(first_ptr), Some(last_ptr)) = (self.first, self.last) else {
+            return;
+        };
+        unsafe {
+            if first_ptr.as_ref().key.borrow() >= key {
+                return;
+            }
+            // Skipped nodes are not counted
+            self.len = None;
+            if last_ptr.as_ref().key.borrow() < key {
+                self.first = None;
+                self.last = None;
+                return;
+            }
+
+            let mut node_ptr = first_ptr;
+            let mut found = None;
+            let mut current = loop {
+                if node_ptr.as_ref().key.borrow() >= key {
+                    found = Some(node_ptr);
+                    break node_ptr.as_ref().left;
+                }
+                match node_ptr.as_ref().parent {
+                    Some(parent_ptr) => node_ptr = parent_ptr,
+                    None => break node_ptr.as_ref().right,
+                }
+            };
+            while let Some(node_ptr) = current {
+                if node_ptr.as_ref().key.borrow() >= key {
+                    found = Some(node_ptr);
+                    current = node_ptr.as_ref().left;
+                } else {
+                    current = node_ptr.as_ref().right;
+                }
+            }
+
+            // Last key is not less than given key, so a node has been found
+            self.first = found;
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K, V> NodeIter<'a, K, V> {
+    /// Splits the range into two ranges at the common ancestor of its first and last node,
+    /// which is the root of the smallest sub tree containing the range.
+    /// Returns the range unchanged and None if it has less than two nodes.
+    fn split(self) -> (Self, Option<Self>) {
+        match (self.first, self.last) {
+            (Some(first_ptr), Some(last_ptr)) if first_ptr != last_ptr => unsafe {
+                let mid_ptr = Node::common_ancestor(first_ptr, last_ptr);
+                if mid_ptr == first_ptr {
+                    (
+                        NodeIter::new(Some(first_ptr), Some(first_ptr)),
+                        Some(NodeIter::new(Node::successor(first_ptr), Some(last_ptr))),
+                    )
+                } else {
+                    (
+                        NodeIter::new(Some(first_ptr), Node::predecessor(mid_ptr)),
+                        Some(NodeIter::new(Some(mid_ptr), Some(last_ptr))),
+                    )
+                }
+            },
+            _ => (self, None),
+        }
+    }
 }
 
 unsafe impl<'a, K, V> Sync for NodeIter<'a, K, V> {}
@@ -2056,11 +5295,19 @@ impl<K, V> NodeEater<K, V> {
         let node_eater = Self {
             first: map.find_first(),
             last: map.find_last(),
+            len: map.num_nodes,
         };
         map.root.take();
+        map.min.take();
+        map.max.take();
         node_eater
     }
 
+    /// Returns the exact remaining length of the range.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
     /// Pops first node from range, consumes it and returns its key value pair. Returns None if range is empty.
     fn pop_first(&mut self) -> Option<(K, V)> {
         self.pop_first_node()
@@ -2077,6 +5324,7 @@ impl<K, V> NodeEater<K, V> {
     fn pop_first_node(&mut self) -> Link<K, V> {
         let mut first = self.first;
         let node_ptr = first?;
+        self.len -= 1;
         if self.first == self.last {
             // Last remaining node in the range -> end iteration
             self.first = None;
@@ -2133,6 +5381,7 @@ impl<K, V> NodeEater<K, V> {
     fn pop_last_node(&mut self) -> Link<K, V> {
         let mut last = self.last;
         let node_ptr = last?;
+        self.len -= 1;
         if self.last == self.first {
             // Last remaining node in the range -> end iteration
             self.first = None;
@@ -2185,17 +5434,41 @@ impl<K, V> NodeEater<K, V> {
         Some(node_ptr)
     }
 
-    fn postorder<F: FnMut(NodePtr<K, V>)>(&self, f: F) {
-        AvlTreeMap::traverse(self.first, |_| {}, |_| {}, f);
+    /// Drops all nodes before given first and after given last node of the range.
+    /// Drops all nodes if the given range is empty.
+    fn truncate(&mut self, first: Link<K, V>, last: Link<K, V>) {
+        while self.first.is_some() && self.first != first {
+            self.pop_first();
+        }
+        while self.last.is_some() && self.last != last {
+            self.pop_last();
+        }
     }
 }
 
 impl<K, V> Drop for NodeEater<K, V> {
     /// Drops all nodes which have not been consumed.
+    /// Keeps dropping the remaining nodes if dropping an element panics.
     fn drop(&mut self) {
-        self.postorder(|node_ptr| unsafe {
-            Node::destroy(node_ptr);
-        });
+        struct DropGuard<'a, K, V>(&'a mut NodeEater<K, V>);
+
+        impl<K, V> Drop for DropGuard<'_, K, V> {
+            fn drop(&mut self) {
+                while let Some(node_ptr) = self.0.pop_first_node() {
+                    unsafe {
+                        Node::destroy(node_ptr);
+                    }
+                }
+            }
+        }
+
+        while let Some(node_ptr) = self.pop_first_node() {
+            let guard = DropGuard(self);
+            unsafe {
+                Node::destroy(node_ptr);
+            }
+            mem::forget(guard);
+        }
     }
 }
 
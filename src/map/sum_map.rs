@@ -0,0 +1,349 @@
+//! An ordered map to `i64` values which keeps the sum of each sub tree.
+
+use super::{AvlTreeMap, InsertPos, Iter, Link, NodePtr};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::iter::FromIterator;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+
+/// An ordered map from keys to `i64` values, implemented with an AVL tree whose nodes
+/// additionally keep the sum of the values of their sub tree.
+///
+/// Inserts and removes update the sums along the path to the root, so both stay O(log n)
+/// and the sum of the values of any range of keys is computed in O(log n) time.
+/// Sums wrap around on overflow.
+///
+/// ```
+/// use avl::map::AvlTreeSumMap;
+/// let mut map = AvlTreeSumMap::new();
+/// map.insert(1, 10);
+/// map.insert(2, 20);
+/// map.insert(3, 30);
+/// assert_eq!(map.range_sum(2..), 50);
+/// map.remove(&3);
+/// assert_eq!(map.sum(), 30);
+/// ```
+pub struct AvlTreeSumMap<K> {
+    map: AvlTreeMap<K, SumValue>,
+}
+
+/// The value of an entry of a sum map along with the sum of the values of its sub tree.
+#[derive(Clone, Copy)]
+struct SumValue {
+    value: i64,
+    sum: i64,
+}
+
+/// An iterator over the entries of a sum map.
+///
+/// This `struct` is created by the [`iter`] method on [`AvlTreeSumMap`].
+///
+/// [`AvlTreeSumMap`]: struct.AvlTreeSumMap.html
+/// [`iter`]: struct.AvlTreeSumMap.html#method.iter
+pub struct SumMapIter<'a, K> {
+    iter: Iter<'a, K, SumValue>,
+}
+
+// region Implementation of AvlTreeSumMap
+
+impl<K> AvlTreeSumMap<K> {
+    /// Creates an empty sum map.
+    /// No memory is allocated until the first item is inserted.
+    pub const fn new() -> Self {
+        Self {
+            map: AvlTreeMap::new(),
+        }
+    }
+
+    /// Returns true if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Clears the map, deallocating all memory.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&i64>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key).map(|sum_value| &sum_value.value)
+    }
+
+    /// Returns true if the key is in the map, else false.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Inserts a key-value pair into the map and updates the sums in O(log n) time.
+    /// Returns the previous value of the key, or None if the key was not in the map.
+    pub fn insert(&mut self, key: K, value: i64) -> Option<i64>
+    where
+        K: Ord,
+    {
+        match self.map.find_insert_pos(&key) {
+            InsertPos::Vacant { parent, link_ptr } => unsafe {
+                let sum_value = SumValue { value, sum: value };
+                let (node_ptr, _) = self
+                    .map
+                    .insert_entry_at_vacant_pos(parent, link_ptr, key, sum_value);
+                Self::update_sums_to_root(Some(node_ptr));
+                None
+            },
+            InsertPos::Occupied { node_ptr } => unsafe {
+                let old_value = mem::replace(&mut (*node_ptr.as_ptr()).value.value, value);
+                Self::update_sums_to_root(Some(node_ptr));
+                Some(old_value)
+            },
+        }
+    }
+
+    /// Removes a key from the map and updates the sums in O(log n) time.
+    /// Returns the value of the key, or None if the key was not in the map.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<i64>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node_ptr = self.map.find(key)?;
+        // The lowest node whose sub tree changes is the parent of the node that is unlinked,
+        // which is either the removed node or its successor taking its place
+        let changed = unsafe {
+            match node_ptr.as_ref().right {
+                None => node_ptr.as_ref().parent,
+                Some(mut successor_ptr) => {
+                    while let Some(left_ptr) = successor_ptr.as_ref().left {
+                        successor_ptr = left_ptr;
+                    }
+                    match successor_ptr.as_ref().parent {
+                        Some(parent_ptr) if parent_ptr == node_ptr => Some(successor_ptr),
+                        parent => parent,
+                    }
+                }
+            }
+        };
+        let (_, sum_value) = unsafe { self.map.remove_entry_at_occupied_pos(node_ptr) };
+        Self::update_sums_to_root(changed);
+        Some(sum_value.value)
+    }
+
+    /// Returns the sum of all values of the map in O(1) time.
+    pub fn sum(&self) -> i64 {
+        Self::link_sum(self.map.root)
+    }
+
+    /// Returns the sum of the values of the keys in the range in O(log n) time.
+    ///
+    /// The key may be any borrowed form of the map's key type, but the ordering
+    /// on the borrowed form *must* match the ordering on the key type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`.
+    /// Panics if range `start == end` and both bounds are `Excluded`.
+    pub fn range_sum<Q, R>(&self, range: R) -> i64
+    where
+        K: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        if let Err(err) = AvlTreeMap::<K, SumValue>::check_range(&range) {
+            panic!("{}", err);
+        }
+        let before_start = match range.start_bound() {
+            Bound::Included(key) => self.sum_below(key, false),
+            Bound::Excluded(key) => self.sum_below(key, true),
+            Bound::Unbounded => 0,
+        };
+        let up_to_end = match range.end_bound() {
+            Bound::Included(key) => self.sum_below(key, true),
+            Bound::Excluded(key) => self.sum_below(key, false),
+            Bound::Unbounded => self.sum(),
+        };
+        up_to_end.wrapping_sub(before_start)
+    }
+
+    /// Gets an iterator over the entries of the map, sorted by key.
+    pub fn iter(&self) -> SumMapIter<'_, K> {
+        SumMapIter {
+            iter: self.map.iter(),
+        }
+    }
+
+    /// Asserts that the internal tree structure is consistent
+    /// and that each node keeps the sum of the values of its sub tree.
+    #[cfg(any(test, feature = "consistency_check"))]
+    pub fn check_consistency(&self)
+    where
+        K: Ord,
+    {
+        self.map.check_consistency();
+        self.map.postorder(|node_ptr| unsafe {
+            let node = node_ptr.as_ref();
+            let sum = node
+                .value
+                .value
+                .wrapping_add(Self::link_sum(node.left))
+                .wrapping_add(Self::link_sum(node.right));
+            assert_eq!(node.value.sum, sum, "sub tree sum is invalid");
+        });
+    }
+}
+
+impl<K> AvlTreeSumMap<K> {
+    /// Returns the sum of the values of the entries with keys less than,
+    /// or if `inclusive` less than or equal to, given key.
+    fn sum_below<Q>(&self, key: &Q, inclusive: bool) -> i64
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut sum = 0i64;
+        let mut current = self.map.root;
+        while let Some(node_ptr) = current {
+            let node = unsafe { node_ptr.as_ref() };
+            let below = match node.key.borrow().cmp(key) {
+                Ordering::Less => true,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => false,
+            };
+            if below {
+                sum = sum
+                    .wrapping_add(Self::link_sum(node.left))
+                    .wrapping_add(node.value.value);
+                current = node.right;
+            } else {
+                current = node.left;
+            }
+        }
+        sum
+    }
+
+    /// Returns the sum of the values of the sub tree at given link.
+    fn link_sum(link: Link<K, SumValue>) -> i64 {
+        match link {
+            None => 0,
+            Some(node_ptr) => unsafe { node_ptr.as_ref().value.sum },
+        }
+    }
+
+    /// Recomputes the sum of given node from its value and the sums of its children.
+    fn update_sum(mut node_ptr: NodePtr<K, SumValue>) {
+        unsafe {
+            let sum = node_ptr
+                .as_ref()
+                .value
+                .value
+                .wrapping_add(Self::link_sum(node_ptr.as_ref().left))
+                .wrapping_add(Self::link_sum(node_ptr.as_ref().right));
+            node_ptr.as_mut().value.sum = sum;
+        }
+    }
+
+    /// Recomputes the sums from given node up to the root node.
+    /// The children of each node are recomputed first: Nodes rotated off the path while
+    /// rebalancing become children of nodes on the path, with unchanged sub trees below.
+    fn update_sums_to_root(start_from: Link<K, SumValue>) {
+        let mut current = start_from;
+        while let Some(node_ptr) = current {
+            unsafe {
+                if let Some(left_ptr) = node_ptr.as_ref().left {
+                    Self::update_sum(left_ptr);
+                }
+                if let Some(right_ptr) = node_ptr.as_ref().right {
+                    Self::update_sum(right_ptr);
+                }
+                Self::update_sum(node_ptr);
+                current = node_ptr.as_ref().parent;
+            }
+        }
+    }
+}
+
+impl<K> Default for AvlTreeSumMap<K> {
+    /// Creates an empty sum map.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone> Clone for AvlTreeSumMap<K> {
+    fn clone(&self) -> Self {
+        // Cloning keeps the tree structure, so the sums stay valid
+        Self {
+            map: self.map.clone_tree(),
+        }
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for AvlTreeSumMap<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord> FromIterator<(K, i64)> for AvlTreeSumMap<K> {
+    fn from_iter<I: IntoIterator<Item = (K, i64)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord> Extend<(K, i64)> for AvlTreeSumMap<K> {
+    fn extend<I: IntoIterator<Item = (K, i64)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K> Iterator for SumMapIter<'a, K> {
+    type Item = (&'a K, &'a i64);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, sum_value) = self.iter.next()?;
+        Some((key, &sum_value.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K> DoubleEndedIterator for SumMapIter<'_, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, sum_value) = self.iter.next_back()?;
+        Some((key, &sum_value.value))
+    }
+}
+
+impl<K> Clone for SumMapIter<'_, K> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for SumMapIter<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+// endregion Implementation of AvlTreeSumMap
@@ -1,5 +1,6 @@
 //! An ordered set implemented with an AVL tree.
 
+use alloc::vec::Vec;
 use core::borrow::Borrow;
 use core::cmp::Ordering;
 use core::fmt;
@@ -7,7 +8,15 @@ use core::iter::FromIterator;
 use core::ops::{BitAnd, BitOr, BitXor, RangeBounds, Sub};
 
 pub use crate::map;
-use map::{AvlTreeMap, IntoIter as MapIntoIter, Iter as MapIter, Range as MapRange};
+use map::{
+    AvlTreeMap, Drain as MapDrain, ExtractIfInner as MapExtractIfInner, IntoIter as MapIntoIter,
+    Iter as MapIter, MergeJoin as MapMergeJoin, MergeSide, Range as MapRange, RangeError,
+};
+
+#[cfg(feature = "rayon")]
+use map::ParIter as MapParIter;
+#[cfg(feature = "rayon")]
+use rayon::iter::{plumbing::UnindexedConsumer, IntoParallelIterator, ParallelIterator};
 
 /// An ordered set implemented with an AVL tree.
 ///
@@ -21,6 +30,9 @@ use map::{AvlTreeMap, IntoIter as MapIntoIter, Iter as MapIter, Range as MapRang
 /// set.remove(&1);
 /// assert!(!set.contains(&1));
 /// ```
+///
+/// Only operations that look up values, such as `contains`, `insert`, `remove` or `range`,
+/// require `T: Ord`. Creating, clearing and iterating a set works for any value type.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AvlTreeSet<T> {
     map: AvlTreeMap<T, ()>,
@@ -36,11 +48,57 @@ pub struct Range<'a, T> {
     map_range: MapRange<'a, T, ()>,
 }
 
+/// An iterator over the runs of consecutive values of a set.
+///
+/// This `struct` is created by the [`runs`] method on [`AvlTreeSet`].
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+/// [`runs`]: struct.AvlTreeSet.html#method.runs
+pub struct Runs<'a, T, F> {
+    iter: Iter<'a, T>,
+    successor: F,
+}
+
 /// An owning iterator over the values of a set.
 pub struct IntoIter<T> {
     map_into_iter: MapIntoIter<T, ()>,
 }
 
+/// A draining iterator over the values of a set.
+///
+/// This `struct` is created by the [`drain`] method on [`AvlTreeSet`].
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+/// [`drain`]: struct.AvlTreeSet.html#method.drain
+pub struct Drain<'a, T> {
+    map_drain: MapDrain<'a, T, ()>,
+}
+
+/// An iterator which removes and yields the values of a set matching a predicate.
+///
+/// This `struct` is created by the [`extract_if`] method on [`AvlTreeSet`].
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+/// [`extract_if`]: struct.AvlTreeSet.html#method.extract_if
+pub struct ExtractIf<'a, T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    map_inner: MapExtractIfInner<'a, T, ()>,
+    pred: F,
+}
+
+/// A parallel iterator over the values of a set.
+///
+/// This `struct` is created by the `par_iter` method on [`AvlTreeSet`], which is provided by
+/// rayon's `IntoParallelRefIterator` trait. Requires the `rayon` feature.
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+#[cfg(feature = "rayon")]
+pub struct ParIter<'a, T> {
+    map_par_iter: MapParIter<'a, T, ()>,
+}
+
 /// A lazy iterator for the values in the union of two sets.
 ///
 /// This `struct` is created by the [`union`] method on [`AvlTreeSet`].
@@ -88,19 +146,64 @@ pub struct SymmetricDifference<'a, T> {
     rhs_iter: Iter<'a, T>,
 }
 
-impl<T: Ord> AvlTreeSet<T> {
+/// The set(s) a value of a merge has been found in.
+///
+/// This `enum` is yielded by the [`merge`] iterator on [`AvlTreeSet`].
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+/// [`merge`]: struct.AvlTreeSet.html#method.merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Membership {
+    /// The value is only in `self`.
+    OnlyLeft,
+    /// The value is only in `other`.
+    OnlyRight,
+    /// The value is in both sets.
+    Both,
+}
+
+/// A lazy iterator for the values of two sets, classified by the set(s) they are in.
+///
+/// This `struct` is created by the [`merge`] method on [`AvlTreeSet`].
+///
+/// [`AvlTreeSet`]: struct.AvlTreeSet.html
+/// [`merge`]: struct.AvlTreeSet.html#method.merge
+pub struct Merge<'a, T> {
+    map_merge_join: MapMergeJoin<'a, T, ()>,
+}
+
+//region Implementation of AvlTreeSet
+
+impl<T> AvlTreeSet<T> {
     /// Creates an empty set.
     /// No memory is allocated until the first item is inserted.
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             map: AvlTreeMap::new(),
         }
     }
-}
 
-//region Implementation of AvlTreeSet
+    /// Creates an empty set with memory preallocated for at least `capacity` values.
+    ///
+    /// Memory of removed values is kept and reused for subsequent inserts as well,
+    /// as long as the capacity of the set does not exceed `capacity`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: AvlTreeMap::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of values the set can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// Deallocates the memory kept for reuse by removed values,
+    /// keeping only the memory of the values in the set.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
 
-impl<T> AvlTreeSet<T> {
     /// Returns true if the set contains no elements.
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
@@ -111,11 +214,23 @@ impl<T> AvlTreeSet<T> {
         self.map.len()
     }
 
-    /// Clears the set, deallocating all memory.
+    /// Clears the set, deallocating all memory
+    /// except the capacity requested by `with_capacity`, which is kept for reuse.
     pub fn clear(&mut self) {
         self.map.clear();
     }
 
+    /// Clears the set, returning all values as an iterator in sorted order.
+    ///
+    /// The set is empty right away. Like `clear`, only keeps the capacity requested
+    /// by `with_capacity` for reuse.
+    /// Values not consumed by the iterator are dropped when the iterator is dropped.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain {
+            map_drain: self.map.drain(),
+        }
+    }
+
     /// Gets an iterator over the values of the map in sorted order.
     pub fn iter(&self) -> Iter<'_, T> {
         Iter {
@@ -123,6 +238,27 @@ impl<T> AvlTreeSet<T> {
         }
     }
 
+    /// Gets an iterator over the runs of consecutive values of the set, in sorted order.
+    /// Each run is yielded as its first and last value.
+    ///
+    /// Two values are consecutive if `successor` of the smaller one returns the greater one.
+    ///
+    /// ```
+    /// use avl::AvlTreeSet;
+    /// let set: AvlTreeSet<_> = [1, 2, 3, 7, 8, 10].into_iter().collect();
+    /// let runs: Vec<_> = set.runs(|value| value + 1).collect();
+    /// assert_eq!(runs, [(&1, &3), (&7, &8), (&10, &10)]);
+    /// ```
+    pub fn runs<F>(&self, successor: F) -> Runs<'_, T, F>
+    where
+        F: FnMut(&T) -> T,
+    {
+        Runs {
+            iter: self.iter(),
+            successor,
+        }
+    }
+
     /// Returns a reference to the value in the set that is equal to the given value.
     ///
     /// The value may be any borrowed form of the set's value type, but the ordering
@@ -135,6 +271,16 @@ impl<T> AvlTreeSet<T> {
         self.map.get_key_value(value).map(|kv| kv.0)
     }
 
+    /// Returns a reference to the first (smallest) value in the set.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|kv| kv.0)
+    }
+
+    /// Returns a reference to the last (largest) value in the set.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|kv| kv.0)
+    }
+
     /// Returns true if the set contains a value.
     ///
     /// The value may be any borrowed form of the set's value type, but the ordering
@@ -173,6 +319,71 @@ impl<T> AvlTreeSet<T> {
         self.map.remove_entry(value).map(|(k, _)| k)
     }
 
+    /// Removes a value from the set if the predicate returns true for the stored value.
+    /// Returns the value if it was removed.
+    ///
+    /// The value may be any borrowed form of the set's value type, but the ordering
+    /// on the borrowed form *must* match the ordering on the value type.
+    pub fn take_if<Q, F>(&mut self, value: &Q, pred: F) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        F: FnOnce(&T) -> bool,
+    {
+        if self.get(value).is_some_and(pred) {
+            self.take(value)
+        } else {
+            None
+        }
+    }
+
+    /// Creates an iterator which removes and yields the values for which `pred(&value)`
+    /// returns true, in sorted order.
+    ///
+    /// The values are removed lazily while iterating. If the iterator is dropped early,
+    /// the values not visited yet remain in the set.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, T, F>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        ExtractIf {
+            map_inner: self.map.extract_if_inner(),
+            pred,
+        }
+    }
+
+    /// Gets an iterator over the `k` smallest values of the set in ascending order,
+    /// or all values if the set has less than `k` values.
+    ///
+    /// Takes O(log n + k) time to set up the iterator.
+    pub fn k_smallest(&self, k: usize) -> Range<'_, T> {
+        Range {
+            map_range: self.map.range_first_n(k),
+        }
+    }
+
+    /// Gets an iterator over the `k` largest values of the set in ascending order,
+    /// or all values if the set has less than `k` values.
+    /// Use `rev()` to get the largest value first.
+    ///
+    /// Takes O(log n + k) time to set up the iterator.
+    pub fn k_largest(&self, k: usize) -> Range<'_, T> {
+        Range {
+            map_range: self.map.range_last_n(k),
+        }
+    }
+
+    /// Retains only the values specified by the predicate.
+    ///
+    /// Removes all values for which `f(&value)` returns false.
+    /// The values are visited in ascending order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.map.retain(|value, _| f(value));
+    }
+
     /// Gets an iterator over a sub-range of values in the set in sorted order.
     ///
     /// The value may be any borrowed form of the set's value type, but the ordering
@@ -192,21 +403,90 @@ impl<T> AvlTreeSet<T> {
             map_range: self.map.range(range),
         }
     }
+
+    /// Gets an iterator over a range of values in the set, in sorted order.
+    /// Returns an error instead of panicking if the range is invalid.
+    ///
+    /// The value may be any borrowed form of the set's value type, but the ordering
+    /// on the borrowed form *must* match the ordering on the value type.
+    pub fn try_range<Q, R>(&self, range: R) -> Result<Range<'_, T>, RangeError>
+    where
+        T: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        Ok(Range {
+            map_range: self.map.try_range(range)?,
+        })
+    }
 }
 
 impl<T: Ord> AvlTreeSet<T> {
+    /// Creates a set from values in strictly ascending order, e.g. when deserializing
+    /// already sorted data. The order is checked in debug builds only.
+    ///
+    /// Builds a balanced tree in O(n) time, see [`AvlTreeMap::extend_sorted_greater`].
+    ///
+    /// [`AvlTreeMap::extend_sorted_greater`]: ../map/struct.AvlTreeMap.html#method.extend_sorted_greater
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut map = AvlTreeMap::new();
+        map.extend_sorted_greater(iter.into_iter().map(|value| (value, ())));
+        Self { map }
+    }
+
+    /// Creates a set from a range of values, e.g. integers, building a balanced tree in O(n) time.
+    ///
+    /// ```
+    /// use avl::AvlTreeSet;
+    /// let set = AvlTreeSet::from_range(0..100);
+    /// assert!(set.iter().copied().eq(0..100));
+    /// ```
+    pub fn from_range(range: core::ops::Range<T>) -> Self
+    where
+        core::ops::Range<T>: Iterator<Item = T>,
+    {
+        Self::from_sorted_iter(range)
+    }
+
     /// Inserts a value into the set.
     pub fn insert(&mut self, value: T) -> bool {
         self.map.insert(value, ()).is_none()
     }
 
+    /// Adds a value to the set, replacing the existing value, if any, that is equal to the given one.
+    /// Returns the replaced value.
+    ///
+    /// The stored value is swapped in place, the tree structure is not changed.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        self.map.replace_key(value, ())
+    }
+
     /// Moves all values from other into self, leaving other empty.
     pub fn append(&mut self, other: &mut Self) {
         self.map.append(&mut other.map);
     }
 
+    /// Clones the values of the iterator into the set, like `extend`.
+    ///
+    /// Unlike the `Extend` implementation for references, this only requires `Clone`.
+    pub fn extend_cloned<'a, I>(&mut self, iter: I)
+    where
+        T: Clone + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        self.extend(iter.into_iter().cloned());
+    }
+
     /// Splits the collection into two at the given key. Returns everything after the given key,
     /// including the key.
+    ///
+    /// Cutting and joining the tree takes O(log n) time. Counting the values of both parts takes
+    /// additional time linear in the size of the smaller part, see [`AvlTreeMap::split_off`].
+    ///
+    /// [`AvlTreeMap::split_off`]: ../map/struct.AvlTreeMap.html#method.split_off
     pub fn split_off<Q>(&mut self, key: &Q) -> Self
     where
         T: Borrow<Q>,
@@ -217,6 +497,29 @@ impl<T: Ord> AvlTreeSet<T> {
         }
     }
 
+    /// Removes the values within the given range from the set and returns them
+    /// as an iterator in sorted order. The values outside of the range are kept.
+    ///
+    /// The values are removed right away, see [`AvlTreeMap::remove_range`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`.
+    /// Panics if range `start == end` and both bounds are `Excluded`.
+    ///
+    /// [`AvlTreeMap::remove_range`]: ../map/struct.AvlTreeMap.html#method.remove_range
+    pub fn drain_range<Q, R>(&mut self, range: R) -> IntoIter<T>
+    where
+        T: Borrow<Q>,
+        R: RangeBounds<Q>,
+        Q: Ord + ?Sized,
+    {
+        Self {
+            map: self.map.remove_range(range),
+        }
+        .into_iter()
+    }
+
     /// Gets an iterator over the values of the union set,
     /// i.e., all values in `self` or `other`, without duplicates,
     /// in ascending order.
@@ -245,6 +548,106 @@ impl<T: Ord> AvlTreeSet<T> {
         SymmetricDifference::new(self, other)
     }
 
+    /// Gets an iterator over the values of both sets, without duplicates,
+    /// in ascending order. Each value tells whether it is only in `self`,
+    /// only in `other`, or in both sets.
+    pub fn merge<'a>(&'a self, other: &'a Self) -> Merge<'a, T> {
+        Merge {
+            map_merge_join: self.map.merge_join(&other.map),
+        }
+    }
+
+    /// Removes all values from `self` that are not in `other`,
+    /// i.e., turns `self` into the intersection of both sets.
+    pub fn intersection_with(&mut self, other: &Self) {
+        self.retain(|value| other.contains(value));
+    }
+
+    /// Removes all values from `self` that are in `other`,
+    /// i.e., turns `self` into the difference of both sets.
+    pub fn difference_with(&mut self, other: &Self) {
+        self.retain(|value| !other.contains(value));
+    }
+
+    /// Moves all values from `other` into `self`,
+    /// i.e., turns `self` into the union of both sets.
+    /// Values that are in both sets are kept from `self`.
+    pub fn union_with(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    /// Consumes both sets and returns their union.
+    /// Values are moved, not cloned; the tree of `self` is reused.
+    pub fn into_union(mut self, other: Self) -> Self {
+        self.union_with(other);
+        self
+    }
+
+    /// Consumes both sets and returns their intersection.
+    /// Values are moved, not cloned; the tree of `self` is reused.
+    pub fn into_intersection(mut self, other: Self) -> Self {
+        self.intersection_with(&other);
+        self
+    }
+
+    /// Consumes both sets and returns their difference.
+    /// Values are moved, not cloned; the tree of `self` is reused.
+    pub fn into_difference(mut self, other: Self) -> Self {
+        self.difference_with(&other);
+        self
+    }
+
+    /// Consumes both sets and returns their symmetric difference.
+    /// Values are moved, not cloned; the tree of `self` is reused.
+    pub fn into_symmetric_difference(mut self, mut other: Self) -> Self {
+        let common: Vec<T> = other.extract_if(|value| self.contains(value)).collect();
+        self.retain(|value| common.binary_search(value).is_err());
+        self.union_with(other);
+        self
+    }
+
+    /// Clears `out` and fills it with the union of `self` and `other`.
+    /// Reuses the memory already allocated by `out`.
+    pub fn union_into(&self, other: &Self, out: &mut Self)
+    where
+        T: Clone,
+    {
+        out.map
+            .replace_with_sorted(self.union(other).map(|value| (value.clone(), ())));
+    }
+
+    /// Clears `out` and fills it with the intersection of `self` and `other`.
+    /// Reuses the memory already allocated by `out`.
+    pub fn intersection_into(&self, other: &Self, out: &mut Self)
+    where
+        T: Clone,
+    {
+        out.map
+            .replace_with_sorted(self.intersection(other).map(|value| (value.clone(), ())));
+    }
+
+    /// Clears `out` and fills it with the difference of `self` and `other`.
+    /// Reuses the memory already allocated by `out`.
+    pub fn difference_into(&self, other: &Self, out: &mut Self)
+    where
+        T: Clone,
+    {
+        out.map
+            .replace_with_sorted(self.difference(other).map(|value| (value.clone(), ())));
+    }
+
+    /// Clears `out` and fills it with the symmetric difference of `self` and `other`.
+    /// Reuses the memory already allocated by `out`.
+    pub fn symmetric_difference_into(&self, other: &Self, out: &mut Self)
+    where
+        T: Clone,
+    {
+        out.map.replace_with_sorted(
+            self.symmetric_difference(other)
+                .map(|value| (value.clone(), ())),
+        );
+    }
+
     /// Returns `true` if `self` has no elements in common with `other`.
     /// This is equivalent to checking for an empty intersection.
     pub fn is_disjoint(&self, other: &Self) -> bool {
@@ -257,9 +660,26 @@ impl<T: Ord> AvlTreeSet<T> {
         if self.len() > other.len() {
             return false;
         }
-        for v in self {
-            if !other.contains(v) {
-                return false;
+        // Walk both sets in order, skipping ahead in other where it has extra values
+        let mut other_range = other.range(..);
+        for value in self {
+            loop {
+                match other_range.peek() {
+                    None => return false,
+                    Some(other_value) => match value.cmp(other_value) {
+                        Ordering::Equal => {
+                            other_range.next();
+                            break;
+                        }
+                        Ordering::Less => return false,
+                        Ordering::Greater => {
+                            other.map.reset_range_start_bound_included(
+                                &mut other_range.map_range,
+                                value,
+                            );
+                        }
+                    },
+                }
             }
         }
         true
@@ -271,14 +691,70 @@ impl<T: Ord> AvlTreeSet<T> {
         other.is_subset(self)
     }
 
+    /// Returns `true` if the set contains all of the given values.
+    ///
+    /// The values have to be sorted in ascending order. They are matched against the set
+    /// in a single pass, which is faster than calling [`contains`] for large batches.
+    ///
+    /// [`contains`]: #method.contains
+    pub fn contains_all<'a, Q, I>(&self, sorted_values: I) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        const MAX_STEPS: usize = 2;
+        let mut range = self.range::<Q, _>(..);
+        let mut prev_value: Option<&Q> = None;
+        for value in sorted_values {
+            debug_assert!(prev_value <= Some(value), "values are not sorted");
+            prev_value = Some(value);
+            // Step through close values, seek from the root if the value is further ahead
+            let mut steps = 0;
+            loop {
+                match range.peek() {
+                    None => return false,
+                    Some(set_value) => match value.cmp(set_value.borrow()) {
+                        Ordering::Equal => break,
+                        Ordering::Less => return false,
+                        Ordering::Greater if steps < MAX_STEPS => {
+                            range.next();
+                            steps += 1;
+                        }
+                        Ordering::Greater => {
+                            self.map
+                                .reset_range_start_bound_included(&mut range.map_range, value);
+                        }
+                    },
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns `true` if the internal tree structure is consistent,
+    /// see [`AvlTreeMap::is_consistent`].
+    ///
+    /// [`AvlTreeMap::is_consistent`]: ../map/struct.AvlTreeMap.html#method.is_consistent
+    pub fn is_consistent(&self) -> bool {
+        self.map.is_consistent()
+    }
+
     /// Asserts that the internal tree structure is consistent.
     #[cfg(any(test, feature = "consistency_check"))]
     pub fn check_consistency(&self) {
         self.map.check_consistency()
     }
+
+    /// Returns `true` if both sets have an identical tree structure,
+    /// i.e. equal values at the same positions with the same heights.
+    #[cfg(any(test, feature = "consistency_check"))]
+    pub fn same_shape(&self, other: &Self) -> bool {
+        self.map.same_shape(&other.map)
+    }
 }
 
-impl<T: Ord> Default for AvlTreeSet<T> {
+impl<T> Default for AvlTreeSet<T> {
     /// Creates an empty set.
     fn default() -> Self {
         Self::new()
@@ -319,6 +795,34 @@ impl<T> IntoIterator for AvlTreeSet<T> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a, T: Ord + arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for AvlTreeSet<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let values: Vec<T> = u.arbitrary()?;
+        Ok(values.into_iter().collect())
+    }
+
+    fn arbitrary_take_rest(u: arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let values: Vec<T> = arbitrary::Arbitrary::arbitrary_take_rest(u)?;
+        Ok(values.into_iter().collect())
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <Vec<T> as arbitrary::Arbitrary>::size_hint(depth)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Sync> IntoParallelIterator for &'a AvlTreeSet<T> {
+    type Item = &'a T;
+    type Iter = ParIter<'a, T>;
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter {
+            map_par_iter: (&self.map).into_par_iter(),
+        }
+    }
+}
+
 impl<T: Ord> Extend<T> for AvlTreeSet<T> {
     fn extend<I>(&mut self, iter: I)
     where
@@ -348,7 +852,9 @@ impl<T: Ord + Clone> BitOr<&AvlTreeSet<T>> for &AvlTreeSet<T> {
 
     /// Returns the union of `self` and `rhs` as a new set.
     fn bitor(self, rhs: &AvlTreeSet<T>) -> AvlTreeSet<T> {
-        self.union(rhs).cloned().collect()
+        let mut set = AvlTreeSet::new();
+        self.union_into(rhs, &mut set);
+        set
     }
 }
 
@@ -357,7 +863,9 @@ impl<T: Ord + Clone> BitAnd<&AvlTreeSet<T>> for &AvlTreeSet<T> {
 
     /// Returns the intersection of `self` and `rhs` as a new set.
     fn bitand(self, rhs: &AvlTreeSet<T>) -> AvlTreeSet<T> {
-        self.intersection(rhs).cloned().collect()
+        let mut set = AvlTreeSet::new();
+        self.intersection_into(rhs, &mut set);
+        set
     }
 }
 
@@ -366,7 +874,9 @@ impl<T: Ord + Clone> Sub<&AvlTreeSet<T>> for &AvlTreeSet<T> {
 
     /// Returns the difference of `self` and `rhs` as a new set.
     fn sub(self, rhs: &AvlTreeSet<T>) -> AvlTreeSet<T> {
-        self.difference(rhs).cloned().collect()
+        let mut set = AvlTreeSet::new();
+        self.difference_into(rhs, &mut set);
+        set
     }
 }
 
@@ -375,7 +885,9 @@ impl<T: Ord + Clone> BitXor<&AvlTreeSet<T>> for &AvlTreeSet<T> {
 
     /// Returns the symmetric difference of `self` and `rhs` as a new set.
     fn bitxor(self, rhs: &AvlTreeSet<T>) -> AvlTreeSet<T> {
-        self.symmetric_difference(rhs).cloned().collect()
+        let mut set = AvlTreeSet::new();
+        self.symmetric_difference_into(rhs, &mut set);
+        set
     }
 }
 
@@ -383,11 +895,66 @@ impl<T: Ord + Clone> BitXor<&AvlTreeSet<T>> for &AvlTreeSet<T> {
 
 //region Implementation of iteators
 
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map_drain.next().map(|(value, _)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_drain.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.map_drain.next_back().map(|(value, _)| value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Drain<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map_drain.fmt_keys(f)
+    }
+}
+
+impl<T, F> Iterator for ExtractIf<'_, T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+        self.map_inner
+            .next(&mut |value: &T, _: &mut ()| pred(value))
+            .map(|(value, _)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_inner.size_hint()
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for ExtractIf<'_, T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map_inner.remaining().fmt_keys(f)
+    }
+}
+
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
         self.map_iter.next().map(|(k, _)| k)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_iter.size_hint()
+    }
+
+    sorted_iterator_methods!();
 }
 
 impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
@@ -412,9 +979,56 @@ impl<T: fmt::Debug> fmt::Debug for Iter<'_, T> {
 }
 
 impl<'a, T> Iter<'a, T> {
-    fn peek(&self) -> Option<<Self as Iterator>::Item> {
+    /// Peeks at next value without advancing the iterator.
+    /// Returns the same item a subsequent call to `next` would return.
+    pub fn peek(&self) -> Option<<Self as Iterator>::Item> {
         self.map_iter.peek().map(|(k, _)| k)
     }
+
+    /// Peeks at next value from the back without advancing the iterator.
+    /// Returns the same item a subsequent call to `next_back` would return.
+    pub fn peek_back(&self) -> Option<<Self as Iterator>::Item> {
+        self.map_iter.peek_back().map(|(k, _)| k)
+    }
+
+    /// Advances the iterator to the first remaining value not less than given value,
+    /// in O(log n) time. Does nothing if the next value is already not less.
+    ///
+    /// The value may be any borrowed form of the set's value type, but the ordering
+    /// on the borrowed form *must* match the ordering on the value type.
+    pub fn seek_forward<Q>(&mut self, value: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map_iter.seek_forward(value);
+    }
+}
+
+impl<'a, T, F> Iterator for Runs<'a, T, F>
+where
+    T: PartialEq,
+    F: FnMut(&T) -> T,
+{
+    type Item = (&'a T, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.iter.next()?;
+        let mut last = first;
+        while let Some(value) = self.iter.peek() {
+            if (self.successor)(last) != *value {
+                break;
+            }
+            last = value;
+            self.iter.next();
+        }
+        Some((first, last))
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Runs<'_, T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Runs").field("iter", &self.iter).finish()
+    }
 }
 
 impl<'a, T> Iterator for Range<'a, T> {
@@ -422,6 +1036,12 @@ impl<'a, T> Iterator for Range<'a, T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.map_range.next().map(|(k, _)| k)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_range.size_hint()
+    }
+
+    sorted_iterator_methods!();
 }
 
 impl<'a, T> DoubleEndedIterator for Range<'a, T> {
@@ -446,9 +1066,30 @@ impl<T: fmt::Debug> fmt::Debug for Range<'_, T> {
 }
 
 impl<'a, T> Range<'a, T> {
-    fn peek(&self) -> Option<<Self as Iterator>::Item> {
+    /// Peeks at next value without advancing the iterator.
+    /// Returns the same item a subsequent call to `next` would return.
+    pub fn peek(&self) -> Option<<Self as Iterator>::Item> {
         self.map_range.peek().map(|(k, _)| k)
     }
+
+    /// Peeks at next value from the back without advancing the iterator.
+    /// Returns the same item a subsequent call to `next_back` would return.
+    pub fn peek_back(&self) -> Option<<Self as Iterator>::Item> {
+        self.map_range.peek_back().map(|(k, _)| k)
+    }
+
+    /// Advances the iterator to the first remaining value not less than given value,
+    /// in O(log n) time. Does nothing if the next value is already not less.
+    ///
+    /// The value may be any borrowed form of the set's value type, but the ordering
+    /// on the borrowed form *must* match the ordering on the value type.
+    pub fn seek_forward<Q>(&mut self, value: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map_range.seek_forward(value);
+    }
 }
 
 impl<T> Iterator for IntoIter<T> {
@@ -456,6 +1097,10 @@ impl<T> Iterator for IntoIter<T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.map_into_iter.next().map(|(k, _)| k)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_into_iter.size_hint()
+    }
 }
 
 impl<T> DoubleEndedIterator for IntoIter<T> {
@@ -470,6 +1115,24 @@ impl<T: fmt::Debug> fmt::Debug for IntoIter<T> {
     }
 }
 
+#[cfg(feature = "rayon")]
+impl<'a, T: Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = &'a T;
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.map_par_iter.map(|(k, _)| k).drive_unindexed(consumer)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: fmt::Debug> fmt::Debug for ParIter<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map_par_iter.fmt_keys(f)
+    }
+}
+
 impl<'a, T: Ord> Union<'a, T> {
     fn new(lhs: &'a AvlTreeSet<T>, rhs: &'a AvlTreeSet<T>) -> Self {
         Self {
@@ -528,6 +1191,76 @@ impl<'a, T: Ord> Iterator for Union<'a, T> {
     }
 }
 
+impl<'a, T: Ord> DoubleEndedIterator for Union<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match (self.lhs_iter.peek_back(), self.rhs_iter.peek_back()) {
+            (None, None) => None,
+            (Some(lhs), None) => {
+                self.lhs_iter.next_back();
+                Some(lhs)
+            }
+            (None, Some(rhs)) => {
+                self.rhs_iter.next_back();
+                Some(rhs)
+            }
+            (Some(lhs), Some(rhs)) => match lhs.cmp(rhs) {
+                Ordering::Greater => {
+                    self.lhs_iter.next_back();
+                    Some(lhs)
+                }
+                Ordering::Equal => {
+                    self.lhs_iter.next_back();
+                    self.rhs_iter.next_back();
+                    Some(lhs)
+                }
+                Ordering::Less => {
+                    self.rhs_iter.next_back();
+                    Some(rhs)
+                }
+            },
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for Merge<'a, T> {
+    type Item = (&'a T, Membership);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, side) = self.map_merge_join.next()?;
+        Some((value, Membership::from_side(side)))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Merge<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (value, side) = self.map_merge_join.next_back()?;
+        Some((value, Membership::from_side(side)))
+    }
+}
+
+impl<T> Clone for Merge<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            map_merge_join: self.map_merge_join.clone(),
+        }
+    }
+}
+
+impl<T: Ord + fmt::Debug> fmt::Debug for Merge<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl Membership {
+    fn from_side(side: MergeSide<'_, ()>) -> Self {
+        match side {
+            MergeSide::Left(_) => Membership::OnlyLeft,
+            MergeSide::Right(_) => Membership::OnlyRight,
+            MergeSide::Both(_, _) => Membership::Both,
+        }
+    }
+}
+
 impl<'a, T: Ord> Intersection<'a, T> {
     fn new(lhs: &'a AvlTreeSet<T>, rhs: &'a AvlTreeSet<T>) -> Self {
         Self {
@@ -586,6 +1319,33 @@ impl<'a, T: Ord> Iterator for Intersection<'a, T> {
     }
 }
 
+impl<'a, T: Ord> DoubleEndedIterator for Intersection<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match (self.lhs_range.peek_back(), self.rhs_range.peek_back()) {
+                (None, _) | (_, None) => return None,
+                (Some(lhs), Some(rhs)) => match lhs.cmp(rhs) {
+                    Ordering::Equal => {
+                        self.lhs_range.next_back();
+                        self.rhs_range.next_back();
+                        return Some(lhs);
+                    }
+                    Ordering::Greater => {
+                        self.lhs
+                            .map
+                            .reset_range_end_bound_included(&mut self.lhs_range.map_range, rhs);
+                    }
+                    Ordering::Less => {
+                        self.rhs
+                            .map
+                            .reset_range_end_bound_included(&mut self.rhs_range.map_range, lhs);
+                    }
+                },
+            }
+        }
+    }
+}
+
 impl<'a, T: Ord> Difference<'a, T> {
     fn new(lhs: &'a AvlTreeSet<T>, rhs: &'a AvlTreeSet<T>) -> Self {
         Self {
@@ -644,6 +1404,35 @@ impl<'a, T: Ord> Iterator for Difference<'a, T> {
     }
 }
 
+impl<'a, T: Ord> DoubleEndedIterator for Difference<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match (self.lhs_range.peek_back(), self.rhs_range.peek_back()) {
+                (None, _) => return None,
+                (Some(lhs), None) => {
+                    self.lhs_range.next_back();
+                    return Some(lhs);
+                }
+                (Some(lhs), Some(rhs)) => match lhs.cmp(rhs) {
+                    Ordering::Equal => {
+                        self.lhs_range.next_back();
+                        self.rhs_range.next_back();
+                    }
+                    Ordering::Greater => {
+                        self.lhs_range.next_back();
+                        return Some(lhs);
+                    }
+                    Ordering::Less => {
+                        self.rhs
+                            .map
+                            .reset_range_end_bound_included(&mut self.rhs_range.map_range, lhs);
+                    }
+                },
+            }
+        }
+    }
+}
+
 impl<'a, T: Ord> SymmetricDifference<'a, T> {
     fn new(lhs: &'a AvlTreeSet<T>, rhs: &'a AvlTreeSet<T>) -> Self {
         Self {
@@ -703,4 +1492,36 @@ impl<'a, T: Ord> Iterator for SymmetricDifference<'a, T> {
     }
 }
 
+impl<'a, T: Ord> DoubleEndedIterator for SymmetricDifference<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match (self.lhs_iter.peek_back(), self.rhs_iter.peek_back()) {
+                (None, None) => return None,
+                (Some(lhs), None) => {
+                    self.lhs_iter.next_back();
+                    return Some(lhs);
+                }
+                (None, Some(rhs)) => {
+                    self.rhs_iter.next_back();
+                    return Some(rhs);
+                }
+                (Some(lhs), Some(rhs)) => match lhs.cmp(rhs) {
+                    Ordering::Greater => {
+                        self.lhs_iter.next_back();
+                        return Some(lhs);
+                    }
+                    Ordering::Equal => {
+                        self.lhs_iter.next_back();
+                        self.rhs_iter.next_back();
+                    }
+                    Ordering::Less => {
+                        self.rhs_iter.next_back();
+                        return Some(rhs);
+                    }
+                },
+            }
+        }
+    }
+}
+
 //endregion Implementation of iteators
@@ -1,10 +1,15 @@
+extern crate std;
+
 use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::cell::Cell;
 use core::ops::Bound;
 
-use super::map::Entry;
+use super::map::{AvlTreeSumMap, Entry, MergeSide, RangeError, RawEntryMut};
+use super::set::Membership;
 use super::{AvlTreeMap, AvlTreeSet};
 
 use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
@@ -47,6 +52,40 @@ fn test_new() {
     assert_eq!(format!("{:?}", set_i8), String::from("{0, 1, 2}"));
 }
 
+#[test]
+fn test_node_size() {
+    use core::mem::size_of;
+
+    // Three links, key and value, plus at most one word for the height and padding
+    let links_size = 3 * size_of::<usize>();
+    assert!(AvlTreeMap::<u64, u64>::node_size() <= links_size + 3 * size_of::<u64>());
+    assert!(AvlTreeMap::<u32, u16>::node_size() <= links_size + size_of::<u64>());
+    assert!(AvlTreeMap::<u8, ()>::node_size() <= links_size + size_of::<usize>());
+}
+
+#[test]
+fn test_memory_usage() {
+    let node_size = AvlTreeMap::<u64, u32>::node_size();
+    let mut map: AvlTreeMap<u64, u32> = AvlTreeMap::new();
+    assert_eq!(map.memory_usage(), 0);
+    for len in 1..=N as u64 {
+        map.insert(len, 0);
+        assert_eq!(map.memory_usage(), len as usize * node_size);
+    }
+
+    map.clear();
+    assert_eq!(map.memory_usage(), 0);
+
+    // Memory kept for reuse is counted until it is deallocated
+    let mut map = AvlTreeMap::<u64, u32>::with_capacity(10);
+    assert_eq!(map.memory_usage(), 10 * node_size);
+    map.extend((0..20).map(|key| (key, 0)));
+    map.clear();
+    assert_eq!(map.memory_usage(), 10 * node_size);
+    map.shrink_to_fit();
+    assert_eq!(map.memory_usage(), 0);
+}
+
 #[test]
 fn test_rebalance() {
     {
@@ -268,7 +307,7 @@ fn test_index_panic() {
     map.insert(1, "foo");
     map.insert(42, "bar");
     map.insert(512, "baz");
-    map[&13];
+    let _ = map[&13];
 }
 
 #[test]
@@ -287,7 +326,7 @@ fn test_clear() {
 
     map.clear();
     assert!(map.is_empty());
-    assert!(map.len() == 0);
+    assert_eq!(map.len(), 0);
 
     for value in &values {
         assert!(map.insert(*value, String::from("bar")).is_none());
@@ -317,7 +356,72 @@ fn test_remove() {
         map.check_consistency();
     }
     assert!(map.is_empty());
-    assert!(map.len() == 0);
+    assert_eq!(map.len(), 0);
+}
+
+#[test]
+fn test_first_last() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut values: Vec<i32> = (0..N).map(|_| rng.gen()).collect();
+
+    let mut map = AvlTreeMap::new();
+    assert!(map.first_key_value().is_none());
+    assert!(map.last_key_value().is_none());
+    for value in &values {
+        map.insert(*value, value.wrapping_add(1));
+    }
+    map.check_consistency();
+
+    values.sort();
+    values.dedup();
+
+    let first = values[0];
+    let last = values[values.len() - 1];
+    assert_eq!(
+        map.first_key_value(),
+        Some((&first, &first.wrapping_add(1)))
+    );
+    assert_eq!(map.last_key_value(), Some((&last, &last.wrapping_add(1))));
+
+    let mut values_iter = values.iter();
+    while map.len() > 1 {
+        let first = values_iter.next().unwrap();
+        assert_eq!(map.pop_first(), Some((*first, first.wrapping_add(1))));
+        map.check_consistency();
+        let last = values_iter.next_back().unwrap();
+        assert_eq!(map.pop_last(), Some((*last, last.wrapping_add(1))));
+        map.check_consistency();
+    }
+    assert_eq!(map.len(), values_iter.len());
+    map.pop_last();
+    assert!(map.pop_first().is_none());
+    assert!(map.pop_last().is_none());
+    map.check_consistency();
+}
+
+#[test]
+fn test_remove_and_successor() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (2 * key, key)).collect();
+
+    assert_eq!(map.remove_and_successor(&10), (Some((10, 5)), Some(12)));
+    assert!(!map.contains_key(&10));
+    map.check_consistency();
+
+    assert_eq!(map.remove_and_successor(&10), (None, Some(12)));
+    assert_eq!(map.remove_and_successor(&13), (None, Some(14)));
+    assert_eq!(map.remove_and_successor(&18), (Some((18, 9)), None));
+    assert_eq!(map.remove_and_successor(&18), (None, None));
+    assert_eq!(map.len(), 8);
+    map.check_consistency();
+
+    // Remove every other entry while walking the map
+    let mut next = map.first_key_value().map(|(key, _)| *key);
+    while let Some(key) = next {
+        let (removed, successor) = map.remove_and_successor(&key);
+        assert_eq!(removed.map(|(key, _)| key), Some(key));
+        next = successor.and_then(|key| map.range(key + 1..).next().map(|(key, _)| *key));
+    }
+    assert_eq!(format!("{:?}", map), "{2: 1, 6: 3, 12: 6, 16: 8}");
 }
 
 #[test]
@@ -361,12 +465,18 @@ fn test_split() {
         .cloned(),
     );
     let offsplit = set.split_off(&115);
+    set.check_consistency();
+    offsplit.check_consistency();
     assert_eq!(format!("{:?}", offsplit), "{115, 116, 1000}");
     assert_eq!(format!("{:?}", set), "{0, 3, 15, 42, 100, 101, 102, 103}");
     let offsplit = set.split_off(&104);
+    set.check_consistency();
+    offsplit.check_consistency();
     assert_eq!(format!("{:?}", offsplit), "{}");
     assert_eq!(format!("{:?}", set), "{0, 3, 15, 42, 100, 101, 102, 103}");
     let offsplit = set.split_off(&0);
+    set.check_consistency();
+    offsplit.check_consistency();
     assert_eq!(
         format!("{:?}", offsplit),
         "{0, 3, 15, 42, 100, 101, 102, 103}"
@@ -374,6 +484,64 @@ fn test_split() {
     assert_eq!(format!("{:?}", set), "{}");
 }
 
+#[test]
+fn test_split_random() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        let len = rng.gen_range(0..N);
+        let mut map: AvlTreeMap<i32, i32> = (0..len).map(|_| (rng.gen_range(0..N), 0)).collect();
+        let expected: Vec<_> = map.keys().cloned().collect();
+        let key = rng.gen_range(-1..=N);
+        let offsplit = map.split_off(&key);
+        map.check_consistency();
+        offsplit.check_consistency();
+        assert!(map.keys().all(|k| *k < key));
+        assert!(offsplit.keys().all(|k| *k >= key));
+        assert!(map.keys().chain(offsplit.keys()).eq(expected.iter()));
+
+        // Split parts stay fully functional
+        let mut map = map;
+        let mut offsplit = offsplit;
+        map.insert(key - N, 0);
+        offsplit.insert(key + N, 0);
+        offsplit.remove(&key);
+        map.check_consistency();
+        offsplit.check_consistency();
+    }
+}
+
+#[test]
+fn test_split_three() {
+    let map: AvlTreeMap<_, _> = (0..100).map(|key| (2 * key, key)).collect();
+
+    let (less, entry, greater) = map.clone().split_three(&100);
+    less.check_consistency();
+    greater.check_consistency();
+    assert!(less
+        .keys()
+        .eq((0..50).map(|key| 2 * key).collect::<Vec<_>>().iter()));
+    assert_eq!(entry, Some((100, 50)));
+    assert!(greater
+        .keys()
+        .eq((51..100).map(|key| 2 * key).collect::<Vec<_>>().iter()));
+
+    let (less, entry, greater) = map.clone().split_three(&101);
+    less.check_consistency();
+    greater.check_consistency();
+    assert!(less
+        .keys()
+        .eq((0..51).map(|key| 2 * key).collect::<Vec<_>>().iter()));
+    assert_eq!(entry, None);
+    assert!(greater
+        .keys()
+        .eq((51..100).map(|key| 2 * key).collect::<Vec<_>>().iter()));
+
+    let (less, entry, greater) = map.split_three(&-1);
+    assert!(less.is_empty());
+    assert_eq!(entry, None);
+    assert_eq!(greater.len(), 100);
+}
+
 #[test]
 fn test_map_entry() {
     let mut map: AvlTreeMap<_, _> = (0..100)
@@ -394,7 +562,7 @@ fn test_map_entry() {
     }
 
     let vacant = map.entry(42);
-    assert_eq!(format!("{:?}", vacant), r"Entry(OccupiedEntry { key: 42 })");
+    assert_eq!(format!("{:?}", vacant), r"Entry(VacantEntry { key: 42 })");
     assert_eq!(vacant.key(), &42);
     if let Entry::Vacant(vacant_entry) = vacant {
         assert_eq!(vacant_entry.key(), &42);
@@ -415,6 +583,29 @@ fn test_map_entry() {
     assert_eq!(map.get(&50), Some(&"baz"));
 }
 
+#[test]
+fn test_get_or_try_insert_with() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key)).collect();
+
+    let mut calls = 0;
+    let value = map.get_or_try_insert_with(5, || -> Result<_, ()> {
+        calls += 1;
+        Ok(42)
+    });
+    assert_eq!(value, Ok(&mut 5));
+    assert_eq!(calls, 0);
+
+    let value = map.get_or_try_insert_with(42, || -> Result<_, ()> { Ok(42) });
+    *value.unwrap() += 1;
+    assert_eq!(map.get(&42), Some(&43));
+
+    let value = map.get_or_try_insert_with(13, || Err("failed"));
+    assert_eq!(value, Err("failed"));
+    assert!(!map.contains_key(&13));
+    assert_eq!(map.len(), 11);
+    map.check_consistency();
+}
+
 #[test]
 fn test_map_iter() {
     use rand::{rngs::StdRng, Rng, SeedableRng};
@@ -538,7 +729,7 @@ fn test_map_iter() {
 
     // Test debug formatting for non owning iterator
     let mut map: AvlTreeMap<i32, &str> = AvlTreeMap::new();
-    map.extend(vec![(1, "one"), (2, "two"), (3, "three")].into_iter());
+    map.extend(vec![(1, "one"), (2, "two"), (3, "three")]);
     assert_eq!(
         format!("{:?}", map.iter()),
         r#"[(1, "one"), (2, "two"), (3, "three")]"#
@@ -582,6 +773,38 @@ fn test_map_iter() {
     assert_eq!(format!("{:?}", map_into_iter), "[]");
 }
 
+#[test]
+fn test_map_iter_with_depth() {
+    //       4
+    //     /   \
+    //    2     6
+    //   / \   / \
+    //  1   3 5   7
+    let map: AvlTreeMap<_, _> = (1..=7).map(|key| (key, key * 10)).collect();
+    assert_eq!(map.height(), 2);
+
+    let entries: Vec<_> = map.iter_with_depth().collect();
+    assert_eq!(entries.len(), 7);
+    assert_eq!(entries[0], (2, &1, &10));
+    assert_eq!(entries[3], (0, &4, &40));
+    assert_eq!(entries[6], (2, &7, &70));
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(depth, _, _)| *depth)
+            .collect::<Vec<_>>(),
+        vec![2, 1, 2, 0, 2, 1, 2]
+    );
+    assert!(map.iter_with_depth().rev().eq(entries.into_iter().rev()));
+    assert_eq!(
+        format!(
+            "{:?}",
+            AvlTreeMap::from_iter([(1, 'a'), (2, 'b')]).iter_with_depth()
+        ),
+        "[(0, 1, 'a'), (1, 2, 'b')]"
+    );
+}
+
 #[test]
 fn test_map_range_iter() {
     use rand::{rngs::StdRng, Rng, SeedableRng};
@@ -731,6 +954,21 @@ fn test_set_iter() {
     assert_eq!(format!("{:?}", set.range(3..3)), "[]");
 }
 
+#[test]
+fn test_set_k_smallest_largest() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let set: AvlTreeSet<i32> = (0..N).map(|_| rng.gen()).collect();
+
+    for k in [0, 1, 2, 10, set.len() - 1, set.len(), set.len() + 1] {
+        assert!(set.k_smallest(k).eq(set.iter().take(k)));
+        assert!(set.k_largest(k).rev().eq(set.iter().rev().take(k)));
+    }
+
+    let empty: AvlTreeSet<i32> = AvlTreeSet::new();
+    assert!(empty.k_smallest(3).next().is_none());
+    assert!(empty.k_largest(3).next().is_none());
+}
+
 #[test]
 fn test_set_ops() {
     let s1: AvlTreeSet<i32> = (0..N).map(|x| 2 * x).collect();
@@ -828,3 +1066,3070 @@ fn test_set_ops() {
                 .collect::<AvlTreeSet<_>>()
         ));
 }
+
+#[test]
+fn test_clone_heights() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen(), rng.gen())).collect();
+
+    // Cloned nodes keep their heights, so the clone rebalances correctly
+    let mut clone = map.clone();
+    clone.check_consistency();
+    for _ in 0..N {
+        clone.insert(rng.gen(), 0);
+        clone.remove(&rng.gen());
+    }
+    clone.check_consistency();
+}
+
+#[test]
+fn test_set_ops_in_place() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..10 {
+        let s1: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let s2: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+
+        let mut intersection = s1.clone();
+        intersection.intersection_with(&s2);
+        intersection.check_consistency();
+        assert!(intersection.iter().eq(s1.intersection(&s2)));
+
+        let mut difference = s1.clone();
+        difference.difference_with(&s2);
+        difference.check_consistency();
+        assert!(difference.iter().eq(s1.difference(&s2)));
+
+        let mut union = s1.clone();
+        union.union_with(s2.clone());
+        union.check_consistency();
+        assert!(union.iter().eq(s1.union(&s2)));
+    }
+
+    let mut set: AvlTreeSet<_> = (0..10).collect();
+    set.retain(|value| value % 3 == 0);
+    assert_eq!(format!("{:?}", set), "{0, 3, 6, 9}");
+
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key)).collect();
+    map.retain(|key, value| {
+        *value *= 2;
+        key % 2 == 1
+    });
+    map.check_consistency();
+    assert_eq!(format!("{:?}", map), "{1: 2, 3: 6, 5: 10, 7: 14, 9: 18}");
+}
+
+#[test]
+fn test_set_ops_rev() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..10 {
+        let s1: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let s2: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+
+        let mut forward: Vec<_> = s1.union(&s2).collect();
+        forward.reverse();
+        assert_eq!(s1.union(&s2).rev().collect::<Vec<_>>(), forward);
+
+        let mut forward: Vec<_> = s1.intersection(&s2).collect();
+        forward.reverse();
+        assert_eq!(s1.intersection(&s2).rev().collect::<Vec<_>>(), forward);
+
+        let mut forward: Vec<_> = s1.difference(&s2).collect();
+        forward.reverse();
+        assert_eq!(s1.difference(&s2).rev().collect::<Vec<_>>(), forward);
+
+        let mut forward: Vec<_> = s1.symmetric_difference(&s2).collect();
+        forward.reverse();
+        assert_eq!(
+            s1.symmetric_difference(&s2).rev().collect::<Vec<_>>(),
+            forward
+        );
+    }
+
+    // Test alternating iteration from both ends
+    let s1: AvlTreeSet<i32> = (0..20).filter(|x| x % 2 == 0).collect();
+    let s2: AvlTreeSet<i32> = (0..20).filter(|x| x % 3 == 0).collect();
+    let mut intersection = s1.intersection(&s2);
+    assert_eq!(intersection.next(), Some(&0));
+    assert_eq!(intersection.next_back(), Some(&18));
+    assert_eq!(intersection.next(), Some(&6));
+    assert_eq!(intersection.next_back(), Some(&12));
+    assert_eq!(intersection.next(), None);
+    assert_eq!(intersection.next_back(), None);
+
+    let mut difference = s1.difference(&s2);
+    assert_eq!(difference.next_back(), Some(&16));
+    assert_eq!(difference.next(), Some(&2));
+    assert_eq!(difference.next_back(), Some(&14));
+    assert_eq!(difference.next(), Some(&4));
+    assert_eq!(difference.collect::<Vec<_>>(), vec![&8, &10]);
+}
+
+#[test]
+fn test_try_map_values_in_place() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, String::from("foo"))).collect();
+
+    let result = map.try_map_values_in_place(|&key, value| {
+        value.push_str("bar");
+        if key == 5 {
+            Err(key)
+        } else {
+            Ok(())
+        }
+    });
+    assert_eq!(result, Err(5));
+    assert!(map.values().all(|value| value == "foo"));
+
+    let result: Result<(), ()> = map.try_map_values_in_place(|_, value| {
+        value.push_str("bar");
+        Ok(())
+    });
+    assert_eq!(result, Ok(()));
+    assert!(map.values().all(|value| value == "foobar"));
+}
+
+#[test]
+fn test_set_subset() {
+    let superset: AvlTreeSet<i32> = (0..10 * N).collect();
+    let subset: AvlTreeSet<i32> = (0..10 * N).filter(|x| x % 7 == 3).collect();
+    assert!(subset.is_subset(&superset));
+    assert!(superset.is_superset(&subset));
+    assert!(!superset.is_subset(&subset));
+    assert!(subset.is_subset(&subset));
+
+    let mut non_subset = subset.clone();
+    non_subset.insert(10 * N);
+    assert!(!non_subset.is_subset(&superset));
+    let mut non_subset = subset.clone();
+    non_subset.insert(-1);
+    assert!(!non_subset.is_subset(&superset));
+    let mut non_subset_superset = superset.clone();
+    non_subset_superset.remove(&(7 * (N / 2) + 3));
+    assert!(!subset.is_subset(&non_subset_superset));
+
+    let empty = AvlTreeSet::new();
+    assert!(empty.is_subset(&superset));
+    assert!(empty.is_subset(&empty));
+    assert!(!subset.is_subset(&empty));
+}
+
+#[test]
+fn test_set_ops_into() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut out = AvlTreeSet::new();
+    for _ in 0..10 {
+        let s1: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let s2: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+
+        s1.union_into(&s2, &mut out);
+        out.check_consistency();
+        assert!(out.iter().eq(s1.union(&s2)));
+
+        s1.intersection_into(&s2, &mut out);
+        out.check_consistency();
+        assert!(out.iter().eq(s1.intersection(&s2)));
+
+        s1.difference_into(&s2, &mut out);
+        out.check_consistency();
+        assert!(out.iter().eq(s1.difference(&s2)));
+
+        s1.symmetric_difference_into(&s2, &mut out);
+        out.check_consistency();
+        assert!(out.iter().eq(s1.symmetric_difference(&s2)));
+    }
+}
+
+#[test]
+fn test_set_into_algebra() {
+    // Vec<u8> values are not Copy, the owning operations must move them
+    let to_set =
+        |values: &[u8]| -> AvlTreeSet<Vec<u8>> { values.iter().map(|v| vec![*v]).collect() };
+    let to_vec = |set: AvlTreeSet<Vec<u8>>| -> Vec<u8> { set.into_iter().map(|v| v[0]).collect() };
+
+    let s1 = to_set(&[1, 2, 3, 5, 8]);
+    let s2 = to_set(&[2, 4, 5, 6]);
+
+    let union = s1.clone().into_union(s2.clone());
+    union.check_consistency();
+    assert_eq!(to_vec(union), [1, 2, 3, 4, 5, 6, 8]);
+
+    let intersection = s1.clone().into_intersection(s2.clone());
+    intersection.check_consistency();
+    assert_eq!(to_vec(intersection), [2, 5]);
+
+    let difference = s1.clone().into_difference(s2.clone());
+    difference.check_consistency();
+    assert_eq!(to_vec(difference), [1, 3, 8]);
+
+    let symmetric_difference = s1.clone().into_symmetric_difference(s2.clone());
+    symmetric_difference.check_consistency();
+    assert_eq!(to_vec(symmetric_difference), [1, 3, 4, 6, 8]);
+
+    let empty = AvlTreeSet::new();
+    assert_eq!(
+        to_vec(s1.clone().into_union(empty.clone())),
+        [1, 2, 3, 5, 8]
+    );
+    assert!(s1.clone().into_intersection(empty.clone()).is_empty());
+    assert_eq!(
+        to_vec(empty.clone().into_symmetric_difference(s2.clone())),
+        [2, 4, 5, 6]
+    );
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..10 {
+        let s1: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let s2: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let expected: Vec<i32> = s1.symmetric_difference(&s2).cloned().collect();
+        let result = s1.into_symmetric_difference(s2);
+        result.check_consistency();
+        assert!(result.iter().eq(expected.iter()));
+    }
+}
+
+#[test]
+fn test_clone_from() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let source: AvlTreeMap<i32, String> = (0..N)
+        .map(|_| {
+            let key = rng.gen_range(0..N);
+            (key, format!("{}", key))
+        })
+        .collect();
+
+    for len in [0, 10, source.len(), 2 * source.len()] {
+        let mut target: AvlTreeMap<i32, String> =
+            (0..len as i32).map(|key| (-key, String::new())).collect();
+        target.clone_from(&source);
+        target.check_consistency();
+        assert_eq!(target, source);
+        assert_eq!(target.len(), source.len());
+    }
+}
+
+#[test]
+fn test_clone_from_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    struct PanicOnClone {
+        panic: bool,
+        value: Rc<()>,
+    }
+
+    impl Clone for PanicOnClone {
+        fn clone(&self) -> Self {
+            if self.panic {
+                panic!("panic on clone");
+            }
+            Self {
+                panic: false,
+                value: Rc::clone(&self.value),
+            }
+        }
+    }
+
+    let value = Rc::new(());
+    let create_value = |panic| PanicOnClone {
+        panic,
+        value: Rc::clone(&value),
+    };
+    let source: AvlTreeMap<i32, PanicOnClone> = (0..N)
+        .map(|key| (key, create_value(key == N / 2)))
+        .collect();
+
+    // Entries cloned before the panic are kept, no nodes or values are leaked
+    for len in [0, 10, N / 2, N, 2 * N] {
+        let mut target: AvlTreeMap<i32, PanicOnClone> =
+            (0..len).map(|key| (-key, create_value(false))).collect();
+        assert!(catch_unwind(AssertUnwindSafe(|| target.clone_from(&source))).is_err());
+        target.check_consistency();
+        assert!(target.keys().copied().eq(0..N / 2));
+        assert_eq!(Rc::strong_count(&value), 1 + N as usize + N as usize / 2);
+    }
+    drop(source);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_iter() {
+    use rayon::prelude::*;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i64> = (0..10 * N)
+        .map(|_| {
+            let key = rng.gen();
+            (key, i64::from(key))
+        })
+        .collect();
+
+    let sum: i64 = map.par_iter().map(|(_, value)| value).sum();
+    assert_eq!(sum, map.values().sum());
+
+    let entries: Vec<_> = map.par_iter().collect();
+    assert!(entries.into_iter().eq(map.iter()));
+
+    let set: AvlTreeSet<i32> = map.keys().cloned().collect();
+    let values: Vec<_> = set.par_iter().collect();
+    assert!(values.into_iter().eq(set.iter()));
+    assert_eq!(set.par_iter().count(), set.len());
+
+    let empty: AvlTreeMap<i32, i64> = AvlTreeMap::new();
+    assert_eq!(empty.par_iter().count(), 0);
+    let set: AvlTreeSet<_> = (1..4).collect();
+    assert_eq!(format!("{:?}", set.par_iter()), "[1, 2, 3]");
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_arbitrary() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for len in [0, 1, 2, 16, 1024, 16 * 1024] {
+        let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+        let mut u = Unstructured::new(&bytes);
+        let map = AvlTreeMap::<u8, u16>::arbitrary(&mut u).unwrap();
+        map.check_consistency();
+
+        let map = AvlTreeMap::<i16, u8>::arbitrary_take_rest(Unstructured::new(&bytes)).unwrap();
+        map.check_consistency();
+
+        let set = AvlTreeSet::<i32>::arbitrary_take_rest(Unstructured::new(&bytes)).unwrap();
+        set.check_consistency();
+    }
+}
+
+#[test]
+fn test_ensure_range() {
+    let mut map: AvlTreeMap<i32, i32> = (0..100).step_by(3).map(|key| (key, -key)).collect();
+    map.ensure_range(0..50);
+    map.check_consistency();
+    map.ensure_range((150..200).rev());
+    map.check_consistency();
+    map.ensure_range(50..150);
+    map.check_consistency();
+    assert_eq!(map.len(), 200);
+    for (&key, &value) in map.iter() {
+        if key < 100 && key % 3 == 0 {
+            assert_eq!(value, -key);
+        } else {
+            assert_eq!(value, 0);
+        }
+    }
+
+    let mut map = AvlTreeMap::<i32, i32>::new();
+    map.ensure_range([]);
+    assert!(map.is_empty());
+    map.ensure_range(0..N);
+    map.check_consistency();
+    assert_eq!(map.len(), N as usize);
+}
+
+#[test]
+fn test_same_shape() {
+    // Ascending inserts of 2^k - 1 keys yield a perfect tree
+    let ascending: AvlTreeMap<_, _> = (1..=127).map(|key| (key, ())).collect();
+    let mut rebuilt: AvlTreeMap<_, _> = (0..500).map(|key| (key, ())).collect();
+    rebuilt.clone_from(&ascending);
+    rebuilt.check_consistency();
+    assert!(rebuilt.same_shape(&ascending));
+    assert!(ascending.clone().same_shape(&ascending));
+
+    // Equal contents, different shapes
+    let ascending: AvlTreeSet<_> = (1..=4).collect();
+    let descending: AvlTreeSet<_> = (1..=4).rev().collect();
+    assert_eq!(ascending, descending);
+    assert!(!ascending.same_shape(&descending));
+    assert!(ascending.same_shape(&ascending.clone()));
+
+    // Equal shapes, different contents
+    let shifted: AvlTreeSet<_> = (2..=5).collect();
+    assert!(!ascending.same_shape(&shifted));
+    assert!(AvlTreeSet::<i32>::new().same_shape(&AvlTreeSet::new()));
+    assert!(!ascending.same_shape(&AvlTreeSet::new()));
+}
+
+#[test]
+fn test_insert_many() {
+    let mut map = AvlTreeMap::new();
+    assert_eq!(map.insert_many((0..10).map(|key| (key, 0))), 10);
+    assert_eq!(map.insert_many((5..15).map(|key| (key, 1))), 5);
+    assert_eq!(map.insert_many([(20, 2), (20, 3), (0, 2)]), 1);
+    assert_eq!(map.insert_many([]), 0);
+    map.check_consistency();
+    assert_eq!(map.len(), 16);
+    assert_eq!(map[&0], 2);
+    assert_eq!(map[&4], 0);
+    assert_eq!(map[&5], 1);
+    assert_eq!(map[&20], 3);
+}
+
+#[test]
+fn test_set_operators() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..10 {
+        let s1: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+        let s2: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+
+        let union = &s1 | &s2;
+        union.check_consistency();
+        assert!(union.iter().eq(s1.union(&s2)));
+
+        let intersection = &s1 & &s2;
+        intersection.check_consistency();
+        assert!(intersection.iter().eq(s1.intersection(&s2)));
+
+        let difference = &s1 - &s2;
+        difference.check_consistency();
+        assert!(difference.iter().eq(s1.difference(&s2)));
+
+        let symmetric_difference = &s1 ^ &s2;
+        symmetric_difference.check_consistency();
+        assert!(symmetric_difference.iter().eq(s1.symmetric_difference(&s2)));
+    }
+}
+
+#[test]
+fn test_try_for_each_ranked_mut() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key * 10, key)).collect();
+    let result = map.try_for_each_ranked_mut(|rank, key, value| {
+        assert_eq!(*key, rank as i32 * 10);
+        if rank == 5 {
+            return Err(rank);
+        }
+        *value = -*value;
+        Ok(())
+    });
+    assert_eq!(result, Err(5));
+    map.check_consistency();
+    assert!(map.values().cloned().eq([0, -1, -2, -3, -4, 5, 6, 7, 8, 9]));
+
+    let mut ranks = Vec::new();
+    let result: Result<(), ()> = map.try_for_each_ranked_mut(|rank, _, _| {
+        ranks.push(rank);
+        Ok(())
+    });
+    assert_eq!(result, Ok(()));
+    assert!(ranks.into_iter().eq(0..10));
+}
+
+#[test]
+fn test_entry_or_insert_with_key() {
+    let mut map = AvlTreeMap::new();
+    map.insert(2, String::from("two"));
+    for key in 0..4 {
+        map.entry(key).or_insert_with_key(|key| format!("{}", key));
+    }
+    map.check_consistency();
+    assert!(map.values().eq(["0", "1", "two", "3"]));
+
+    let value = map
+        .entry(7)
+        .or_insert_with_key(|key| format!("{}", key * 2));
+    value.push('!');
+    assert_eq!(map[&7], "14!");
+}
+
+#[test]
+fn test_compact_into() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for len in [0, 1, 2, 3, 100, 1023, 1024, 10 * N as usize] {
+        let mut map = AvlTreeMap::new();
+        while map.len() < len {
+            let key: i32 = rng.gen();
+            map.insert(key, key.wrapping_mul(3));
+        }
+        map.retain(|key, _| key % 4 != 0);
+        let expected: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+
+        let compact = map.compact_into();
+        compact.check_consistency();
+        assert!(compact
+            .iter()
+            .map(|(&k, &v)| (k, v))
+            .eq(expected.iter().cloned()));
+
+        // Minimum height of a tree with n nodes (height of a leaf is 0)
+        let len = compact.len();
+        let min_height = if len == 0 { 0 } else { len.ilog2() as u16 };
+        assert_eq!(compact.height(), min_height);
+    }
+}
+
+#[test]
+fn test_rebuild() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for len in [0, 1, 2, 3, 100, 1023, 1024, 10 * N as usize] {
+        let mut map = AvlTreeMap::new();
+        while map.len() < len {
+            let key: i32 = rng.gen();
+            map.insert(key, format!("{}", key));
+        }
+        map.retain(|key, _| key % 4 != 0);
+        let expected = map.clone();
+
+        map.rebuild();
+        map.check_consistency();
+        assert_eq!(map, expected);
+        assert_eq!(map.capacity(), map.len());
+        let len = map.len();
+        let min_height = if len == 0 { 0 } else { len.ilog2() as u16 };
+        assert_eq!(map.height(), min_height);
+    }
+}
+
+#[test]
+fn test_occupied_entry_next_prev() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key * 10, key)).collect();
+    let entry = match map.entry(40) {
+        Entry::Occupied(entry) => entry,
+        Entry::Vacant(_) => panic!("Key should be in the map"),
+    };
+    let mut entry = entry.next_entry().unwrap();
+    assert_eq!(*entry.key(), 50);
+    *entry.get_mut() = -5;
+    let mut entry = entry.next_entry().unwrap();
+    entry.insert(-6);
+    assert_eq!(entry.prev_entry().unwrap().remove_entry(), (50, -5));
+    map.check_consistency();
+    assert!(map.values().cloned().eq([0, 1, 2, 3, 4, -6, 7, 8, 9]));
+
+    // Scan and edit forward from a found key
+    let mut current = match map.entry(70) {
+        Entry::Occupied(entry) => Some(entry),
+        Entry::Vacant(_) => None,
+    };
+    while let Some(mut entry) = current {
+        *entry.get_mut() *= 100;
+        current = entry.next_entry();
+    }
+    assert!(map.values().cloned().eq([0, 1, 2, 3, 4, -6, 700, 800, 900]));
+
+    if let Entry::Occupied(entry) = map.entry(0) {
+        assert!(entry.prev_entry().is_none());
+    }
+    if let Entry::Occupied(entry) = map.entry(90) {
+        assert!(entry.next_entry().is_none());
+    }
+}
+
+#[test]
+fn test_vacant_entry_insert_entry() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key * 2, key)).collect();
+    if let Entry::Vacant(entry) = map.entry(7) {
+        let mut entry = entry.insert_entry(-1);
+        assert_eq!(*entry.key(), 7);
+        assert_eq!(*entry.get(), -1);
+        *entry.get_mut() = -7;
+        assert_eq!(entry.prev_entry().unwrap().key(), &6);
+    } else {
+        panic!("Key should not be in the map");
+    }
+    map.check_consistency();
+    assert_eq!(map.get(&7), Some(&-7));
+
+    if let Entry::Vacant(entry) = map.entry(21) {
+        assert_eq!(entry.insert_entry(0).remove_entry(), (21, 0));
+    } else {
+        panic!("Key should not be in the map");
+    }
+    map.check_consistency();
+    assert_eq!(map.len(), 11);
+    assert!(!map.contains_key(&21));
+}
+
+#[test]
+fn test_into_range() {
+    let map: AvlTreeMap<_, _> = (0..100).map(|key| (key, key * 2)).collect();
+    assert!(map
+        .clone()
+        .into_range(40..60)
+        .eq((40..60).map(|key| (key, key * 2))));
+    assert!(map
+        .clone()
+        .into_range(..=10)
+        .rev()
+        .eq((0..=10).rev().map(|key| (key, key * 2))));
+    assert!(map
+        .clone()
+        .into_range(90..)
+        .eq((90..100).map(|key| (key, key * 2))));
+    assert_eq!(map.clone().into_range(..).count(), 100);
+    assert_eq!(map.clone().into_range(200..).count(), 0);
+    assert_eq!(
+        format!("{:?}", map.clone().into_range(3..5)),
+        "[(3, 6), (4, 8)]"
+    );
+
+    // All values are dropped exactly once, whether consumed or not
+    let value = Rc::new(());
+    let map: AvlTreeMap<_, _> = (0..N).map(|key| (key, Rc::clone(&value))).collect();
+    let mut into_range = map.into_range(N / 4..N / 2);
+    assert_eq!(Rc::strong_count(&value), (N / 4) as usize + 1);
+    assert_eq!(into_range.next().unwrap().0, N / 4);
+    assert_eq!(into_range.next_back().unwrap().0, N / 2 - 1);
+    assert_eq!(Rc::strong_count(&value), (N / 4) as usize - 1);
+    drop(into_range);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn test_set_first_last() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut set = AvlTreeSet::new();
+    assert_eq!(set.first(), None);
+    assert_eq!(set.last(), None);
+    for _ in 0..N {
+        set.insert(rng.gen_range(-N..N));
+        let sorted: Vec<_> = set.iter().cloned().collect();
+        assert_eq!(set.first(), sorted.first());
+        assert_eq!(set.last(), sorted.last());
+    }
+    while let Some(&first) = set.first() {
+        set.remove(&first);
+        assert!(set.iter().all(|value| *value > first));
+    }
+}
+
+#[test]
+fn test_extract_if() {
+    let mut set: AvlTreeSet<_> = (0..100).collect();
+    let extracted: Vec<_> = set.extract_if(|value| value % 3 == 0).collect();
+    set.check_consistency();
+    assert!(extracted.into_iter().eq((0..100).step_by(3)));
+    assert!(set
+        .iter()
+        .cloned()
+        .eq((0..100).filter(|value| value % 3 != 0)));
+
+    // Dropping the iterator early keeps the entries not visited yet
+    let mut map: AvlTreeMap<_, _> = (0..100).map(|key| (key, key)).collect();
+    {
+        let mut extract_if = map.extract_if(|_, value| {
+            *value *= 2;
+            *value % 4 == 0
+        });
+        assert_eq!(extract_if.next(), Some((0, 0)));
+        assert_eq!(extract_if.next(), Some((2, 4)));
+        assert_eq!(
+            format!("{:?}", extract_if).get(..16),
+            Some("[(3, 3), (4, 4),")
+        );
+    }
+    map.check_consistency();
+    assert_eq!(map.len(), 98);
+    assert!(map.iter().take(3).eq([(&1, &2), (&3, &3), (&4, &4)]));
+
+    let mut set: AvlTreeSet<_> = (0..5).collect();
+    let mut extract_if = set.extract_if(|value| *value == 1);
+    assert_eq!(extract_if.next(), Some(1));
+    assert_eq!(format!("{:?}", extract_if), "[2, 3, 4]");
+    assert_eq!(extract_if.next(), None);
+    assert_eq!(format!("{:?}", extract_if), "[]");
+}
+
+#[test]
+fn test_set_algebra_crate_root() {
+    let lhs: crate::AvlTreeSet<_> = (0..10).collect();
+    let rhs: crate::AvlTreeSet<_> = (5..15).collect();
+    assert!(lhs.union(&rhs).cloned().eq(0..15));
+    assert!(lhs.intersection(&rhs).cloned().eq(5..10));
+    assert!(lhs.difference(&rhs).cloned().eq(0..5));
+    assert!(lhs
+        .symmetric_difference(&rhs)
+        .cloned()
+        .eq((0..5).chain(10..15)));
+    assert!((&lhs | &rhs).into_iter().eq(0..15));
+    assert!((&lhs & &rhs).into_iter().eq(5..10));
+    assert!((&lhs - &rhs).into_iter().eq(0..5));
+    assert!((&lhs ^ &rhs).into_iter().eq((0..5).chain(10..15)));
+    assert!(!lhs.is_disjoint(&rhs));
+    assert!((&lhs - &rhs).is_disjoint(&rhs));
+    assert!((&lhs & &rhs).is_subset(&lhs));
+    assert!(lhs.is_superset(&(&lhs - &rhs)));
+    assert!(!lhs.is_subset(&rhs));
+}
+
+#[test]
+fn test_set_take_if() {
+    // Ordered by id only, the payload is ignored by comparisons
+    #[derive(Debug)]
+    struct Tagged {
+        id: i32,
+        payload: &'static str,
+    }
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+    impl Eq for Tagged {}
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Tagged {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+    impl core::borrow::Borrow<i32> for Tagged {
+        fn borrow(&self) -> &i32 {
+            &self.id
+        }
+    }
+
+    let mut set = AvlTreeSet::new();
+    set.insert(Tagged {
+        id: 1,
+        payload: "keep",
+    });
+    set.insert(Tagged {
+        id: 2,
+        payload: "drop",
+    });
+
+    assert!(set.take_if(&1, |tagged| tagged.payload == "drop").is_none());
+    assert!(set.take_if(&3, |_| true).is_none());
+    let taken = set.take_if(&2, |tagged| tagged.payload == "drop").unwrap();
+    assert_eq!((taken.id, taken.payload), (2, "drop"));
+    set.check_consistency();
+    assert_eq!(set.len(), 1);
+    assert_eq!(set.get(&1).unwrap().payload, "keep");
+}
+
+#[test]
+fn test_node_reuse() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let keys: Vec<i32> = (0..N).map(|_| rng.gen()).collect();
+
+    // Alternating inserts and removes reuse the nodes of removed entries
+    let mut map = AvlTreeMap::with_capacity(16);
+    assert_eq!(map.capacity(), 16);
+    for key in &keys {
+        map.insert(*key, *key);
+        map.remove(key);
+    }
+    assert_eq!(map.capacity(), 16);
+
+    let mut map = AvlTreeMap::with_capacity(keys.len());
+    map.extend(keys.iter().map(|key| (*key, *key)));
+    assert_eq!(map.capacity(), keys.len());
+    for key in &keys {
+        map.remove(key);
+        map.insert(key.wrapping_add(1), *key);
+    }
+    map.check_consistency();
+    assert_eq!(map.capacity(), keys.len());
+
+    // Clear keeps the nodes for reuse until shrink_to_fit
+    map.clear();
+    map.check_consistency();
+    assert_eq!(map.capacity(), keys.len());
+    for key in &keys {
+        map.insert(*key, *key);
+    }
+    map.check_consistency();
+    assert_eq!(map.capacity(), keys.len());
+    map.clear();
+    map.shrink_to_fit();
+    assert_eq!(map.capacity(), 0);
+
+    // Maps not created with a capacity deallocate the nodes of removed entries right away
+    let mut map: AvlTreeMap<_, _> = keys.iter().map(|key| (*key, *key)).collect();
+    for key in &keys[..keys.len() / 2] {
+        map.remove(key);
+    }
+    map.check_consistency();
+    assert_eq!(map.capacity(), map.len());
+    map.clear();
+    assert_eq!(map.capacity(), 0);
+
+    // Dropping a map with nodes for reuse releases them
+    let value = Rc::new(());
+    let mut map = AvlTreeMap::with_capacity(10);
+    map.extend((0..20).map(|key| (key, Rc::clone(&value))));
+    map.retain(|key, _| key % 2 == 0);
+    map.check_consistency();
+    assert_eq!(Rc::strong_count(&value), 11);
+    assert_eq!(map.capacity(), 10);
+    map.retain(|key, _| *key < 4);
+    assert_eq!(Rc::strong_count(&value), 3);
+    assert_eq!(map.capacity(), 10);
+    drop(map);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn test_const_new() {
+    static EMPTY_MAP: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    static EMPTY_SET: AvlTreeSet<i32> = AvlTreeSet::new();
+    const MAP: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+
+    assert!(EMPTY_MAP.is_empty());
+    assert_eq!(EMPTY_MAP.get(&1), None);
+    assert_eq!(EMPTY_MAP.iter().count(), 0);
+    assert!(!EMPTY_SET.contains(&1));
+
+    let mut map = MAP;
+    map.insert(1, 1);
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn test_unordered_keys() {
+    // Neither key nor value implement Ord
+    #[derive(Debug, PartialEq)]
+    struct Unordered(f64);
+
+    fn count_entries<K, V>(map: &AvlTreeMap<K, V>) -> usize {
+        map.iter().count()
+    }
+
+    let mut map: AvlTreeMap<Unordered, Unordered> = AvlTreeMap::new();
+    assert!(map.is_empty());
+    assert_eq!(map.len(), 0);
+    assert_eq!(count_entries(&map), 0);
+    assert_eq!(map.keys().next(), None);
+    assert_eq!(format!("{:?}", map), "{}");
+    map.clear();
+
+    let map: AvlTreeMap<Unordered, ()> = AvlTreeMap::default();
+    assert_eq!(map.iter().next_back(), None);
+    let map: AvlTreeMap<Unordered, ()> = AvlTreeMap::with_capacity(4);
+    assert_eq!(map.capacity(), 4);
+
+    let set: AvlTreeSet<Unordered> = AvlTreeSet::default();
+    assert!(set.is_empty());
+    assert_eq!(set.iter().count(), 0);
+    assert_eq!(AvlTreeSet::<Unordered>::new().len(), 0);
+
+    // Ordering is only needed for lookups and inserts
+    let map: AvlTreeMap<_, _> = (0..3).map(|key| (key, Unordered(key as f64))).collect();
+    assert_eq!(count_entries(&map), 3);
+    assert_eq!(map.get(&1), Some(&Unordered(1.0)));
+}
+
+#[test]
+fn test_insert_with_stats() {
+    use super::map::InsertStats;
+
+    let mut map = AvlTreeMap::new();
+    let stats = |heights_changed, rotated| InsertStats {
+        heights_changed,
+        rotated,
+    };
+    assert_eq!(map.insert_with_stats(1, ()), (None, stats(0, false)));
+    assert_eq!(map.insert_with_stats(2, ()), (None, stats(1, false)));
+    assert_eq!(map.insert_with_stats(3, ()), (None, stats(1, true)));
+    assert_eq!(map.insert_with_stats(4, ()), (None, stats(2, false)));
+    assert_eq!(map.insert_with_stats(4, ()), (Some(()), stats(0, false)));
+
+    // Sorted inserts of n keys rotate for all but the first and the ones growing the tree
+    for n in [1, 2, 3, 10, 100, 127, 128, N] {
+        let mut map = AvlTreeMap::new();
+        let rotations = (0..n)
+            .filter(|key| map.insert_with_stats(*key, ()).1.rotated)
+            .count();
+        map.check_consistency();
+        assert_eq!(rotations, (n - 1 - n.ilog2() as i32) as usize);
+    }
+}
+
+#[test]
+fn test_insert_checked() {
+    use super::map::InsertResult;
+
+    let mut map = AvlTreeMap::new();
+    let inserted = |rotated| InsertResult::Inserted { rotated };
+    assert_eq!(map.insert_checked(1, "a"), inserted(false));
+    assert_eq!(map.insert_checked(2, "b"), inserted(false));
+    assert_eq!(map.insert_checked(3, "c"), inserted(true));
+    assert_eq!(map.insert_checked(0, "d"), inserted(false));
+    assert_eq!(map.insert_checked(2, "e"), InsertResult::Updated("b"));
+    assert_eq!(map.insert_checked(-1, "f"), inserted(true));
+    map.check_consistency();
+    assert!(map
+        .iter()
+        .eq([(&-1, &"f"), (&0, &"d"), (&1, &"a"), (&2, &"e"), (&3, &"c")]));
+
+    // Agrees with the rotation flag of insert_with_stats
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = AvlTreeMap::new();
+    let mut shadow = AvlTreeMap::new();
+    for _ in 0..N {
+        let key = rng.gen_range(0..N);
+        let (old_value, stats) = shadow.insert_with_stats(key, ());
+        let expected = match old_value {
+            Some(old_value) => InsertResult::Updated(old_value),
+            None => InsertResult::Inserted {
+                rotated: stats.rotated,
+            },
+        };
+        assert_eq!(map.insert_checked(key, ()), expected);
+    }
+    map.check_consistency();
+}
+
+#[test]
+fn test_extend_batch() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for (len, batch_len) in [(0, 0), (0, 10), (10, 1000), (1000, 10), (1000, 1000)] {
+        let mut map = AvlTreeMap::new();
+        let mut expected = Vec::new();
+        for _ in 0..len {
+            let key = rng.gen_range(0..N);
+            map.insert(key, 0);
+            expected.push((key, 0));
+        }
+        let batch: Vec<(i32, i32)> = (1..=batch_len)
+            .map(|value| (rng.gen_range(0..N), value))
+            .collect();
+        map.extend(batch.iter().cloned());
+        map.check_consistency();
+
+        // Later duplicates win
+        let mut expected_map = AvlTreeMap::new();
+        for (key, value) in expected.into_iter().chain(batch) {
+            expected_map.insert(key, value);
+        }
+        assert!(map.iter().eq(expected_map.iter()));
+    }
+
+    // Extending a small map with a large random batch yields a tree of minimum height
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key * N, ())).collect();
+    map.extend((0..1023 - 10).map(|key| (key * N + 1, ())));
+    assert_eq!(map.len(), 1023);
+    assert_eq!(map.height(), 9);
+
+    // A small batch is inserted without buffering, allocating the new nodes only
+    let mut map: AvlTreeMap<_, _> = (0..N).map(|key| (2 * key, key)).collect();
+    map.extend((0..10).map(|key| (2 * key + 1, key)));
+    map.check_consistency();
+    assert_eq!(map.len(), N as usize + 10);
+
+    // A batch of unknown size is inserted entry by entry as well
+    map.extend((0..N).filter(|key| key % 3 == 0).map(|key| (2 * key, -key)));
+    map.check_consistency();
+    assert_eq!(map.len(), N as usize + 10);
+    assert_eq!(map.get(&6), Some(&-3));
+}
+
+#[test]
+fn test_drain() {
+    let mut set: AvlTreeSet<_> = (0..100).collect();
+    let mut drain = set.drain();
+    assert_eq!(drain.next(), Some(0));
+    assert_eq!(drain.next_back(), Some(99));
+    assert!(drain.take(3).eq(1..4));
+    set.check_consistency();
+    assert!(set.is_empty());
+    set.insert(42);
+    assert!(set.iter().eq([42].iter()));
+
+    let mut set: AvlTreeSet<_> = (0..5).collect();
+    let mut drain = set.drain();
+    drain.next();
+    assert_eq!(format!("{:?}", drain), "[1, 2, 3, 4]");
+
+    // Unconsumed entries are dropped, only the capacity requested by with_capacity is kept
+    let value = Rc::new(());
+    let mut map: AvlTreeMap<_, _> = (0..N).map(|key| (key, Rc::clone(&value))).collect();
+    let drained: Vec<_> = map.drain().take(10).map(|(key, _)| key).collect();
+    assert!(drained.into_iter().eq(0..10));
+    assert_eq!(Rc::strong_count(&value), 1);
+    assert!(map.is_empty());
+    assert_eq!(map.capacity(), 0);
+    map.check_consistency();
+    let mut map = AvlTreeMap::with_capacity(10);
+    map.extend((0..N).map(|key| (key, Rc::clone(&value))));
+    drop(map.drain());
+    assert_eq!(Rc::strong_count(&value), 1);
+    assert!(map.is_empty());
+    assert_eq!(map.capacity(), 10);
+    map.check_consistency();
+    map.extend((0..N).map(|key| (key, Rc::clone(&value))));
+    assert!(map.drain().rev().map(|(key, _)| key).eq((0..N).rev()));
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn test_peek() {
+    let map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key * 10)).collect();
+    let mut iter = map.iter();
+    while let Some(peeked) = iter.peek() {
+        assert_eq!(iter.peek_back(), Some((&9, &90)));
+        assert_eq!(Some(peeked), iter.next());
+    }
+    assert_eq!(iter.peek_back(), None);
+
+    let mut range = map.range(3..7);
+    assert_eq!(range.peek(), Some((&3, &30)));
+    assert_eq!(range.peek_back(), Some((&6, &60)));
+    assert_eq!(range.peek_back(), range.next_back());
+    assert_eq!(range.peek(), range.next());
+    assert!(range.eq([(&4, &40), (&5, &50)]));
+
+    let set: AvlTreeSet<_> = (0..10).collect();
+    let mut iter = set.iter();
+    assert_eq!(iter.peek(), iter.next());
+    assert_eq!(iter.peek_back(), iter.next_back());
+    assert_eq!(iter.peek(), Some(&1));
+    let mut range = set.range(5..);
+    assert_eq!(range.peek(), range.next());
+    assert_eq!(range.peek_back(), Some(&9));
+    assert_eq!(set.range(20..).peek(), None);
+}
+
+#[test]
+fn test_append_duplicates_and_disjoint() {
+    // Values of other win for duplicate keys
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, "self")).collect();
+    let mut other: AvlTreeMap<_, _> = (5..15).map(|key| (key, "other")).collect();
+    map.append(&mut other);
+    map.check_consistency();
+    other.check_consistency();
+    assert!(other.is_empty());
+    assert_eq!(map.len(), 15);
+    assert!(map
+        .iter()
+        .all(|(key, value)| (*key < 5) == (*value == "self")));
+
+    let mut map: AvlTreeMap<_, _> = (0..1000).map(|key| (key, "self")).collect();
+    let mut other: AvlTreeMap<_, _> = [(3, "other"), (2000, "other")].into_iter().collect();
+    map.append(&mut other);
+    map.check_consistency();
+    assert_eq!((map[&3], map[&2000], map.len()), ("other", "other", 1001));
+
+    // Disjoint key ranges of all sizes in both directions
+    for lhs_len in [0, 1, 2, 3, 10, 100] {
+        for rhs_len in [0, 1, 2, 3, 10, 100] {
+            let lhs: AvlTreeMap<_, _> = (0..lhs_len).map(|key| (key, ())).collect();
+            let rhs: AvlTreeMap<_, _> = (lhs_len..lhs_len + rhs_len).map(|key| (key, ())).collect();
+
+            let mut map = lhs.clone();
+            let mut other = rhs.clone();
+            map.append(&mut other);
+            map.check_consistency();
+            other.check_consistency();
+            assert!(other.is_empty());
+            assert!(map.keys().cloned().eq(0..lhs_len + rhs_len));
+
+            let mut map = rhs;
+            let mut other = lhs;
+            map.append(&mut other);
+            map.check_consistency();
+            other.check_consistency();
+            assert!(other.is_empty());
+            assert!(map.keys().cloned().eq(0..lhs_len + rhs_len));
+
+            // Other stays usable
+            other.insert(0, ());
+            other.check_consistency();
+        }
+    }
+}
+
+#[test]
+fn test_set_eq_ord() {
+    let set = |values: &[i32]| values.iter().cloned().collect::<AvlTreeSet<_>>();
+
+    assert_eq!(set(&[1, 2]), set(&[2, 1]));
+    assert_ne!(set(&[1, 2]), set(&[1, 2, 3]));
+    assert_ne!(set(&[1, 2, 3]), set(&[1, 2]));
+    assert_ne!(set(&[]), set(&[1]));
+    assert!(set(&[1, 2, 3]) < set(&[1, 2, 4]));
+    assert!(set(&[1, 2]) < set(&[1, 2, 3]));
+    assert!(set(&[1, 3]) > set(&[1, 2, 3]));
+    assert!(set(&[]) < set(&[i32::MIN]));
+
+    // Lexicographic order of the sorted values, regardless of insertion order
+    let mut sets = vec![
+        set(&[3, 1]),
+        set(&[2]),
+        set(&[1, 2, 3]),
+        set(&[]),
+        set(&[1, 2]),
+    ];
+    sets.sort();
+    assert_eq!(
+        format!("{:?}", sets),
+        "[{}, {1, 2}, {1, 2, 3}, {1, 3}, {2}]"
+    );
+    assert_eq!(set(&[2, 1]).cmp(&set(&[1, 2])), core::cmp::Ordering::Equal);
+
+    // Maps compare by entries
+    let map: AvlTreeMap<_, _> = (0..3).map(|key| (key, key)).collect();
+    let longer: AvlTreeMap<_, _> = (0..4).map(|key| (key, key)).collect();
+    assert_ne!(map, longer);
+    assert_ne!(longer, map);
+    assert!(map < longer);
+}
+
+#[test]
+fn test_get2_mut() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, 100)).collect();
+    if let Some((from, to)) = map.get2_mut(&3, &7) {
+        *from -= 30;
+        *to += 30;
+    }
+    assert_eq!((map[&3], map[&7]), (70, 130));
+    assert_eq!(map.values().sum::<i32>(), 1000);
+
+    let (lhs, rhs) = map.get2_mut(&9, &0).unwrap();
+    core::mem::swap(lhs, rhs);
+
+    assert_eq!(map.get2_mut(&3, &10), None);
+    assert_eq!(map.get2_mut(&-1, &3), None);
+    assert_eq!(map.get2_mut(&5, &5), None);
+
+    let mut map: AvlTreeMap<String, i32> = AvlTreeMap::new();
+    map.insert(String::from("a"), 1);
+    map.insert(String::from("b"), 2);
+    let (a, b) = map.get2_mut("a", "b").unwrap();
+    core::mem::swap(a, b);
+    assert_eq!((map["a"], map["b"]), (2, 1));
+}
+
+#[test]
+fn test_fold_in_order() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i64> = (0..N)
+        .map(|_| (rng.gen(), rng.gen_range(0..N).into()))
+        .collect();
+    let sum = map.fold_in_order(0, |acc, _, value| acc + value);
+    assert_eq!(sum, map.values().sum());
+    let keys = map.fold_in_order(Vec::new(), |mut keys, key, _| {
+        keys.push(*key);
+        keys
+    });
+    assert!(keys.iter().eq(map.keys()));
+    assert_eq!(
+        AvlTreeMap::<i32, i32>::new().fold_in_order(42, |_, _, _| 0),
+        42
+    );
+}
+
+#[test]
+fn test_raw_entry_mut() {
+    let mut map: AvlTreeMap<String, usize> = AvlTreeMap::new();
+    for word in ["b", "a", "c", "a", "b", "a"] {
+        match map.raw_entry_mut().from_key(word) {
+            RawEntryMut::Occupied(mut o) => *o.get_mut() += 1,
+            RawEntryMut::Vacant(v) => {
+                let (key, value) = v.insert(String::from(word), 1);
+                assert_eq!(key, word);
+                assert_eq!(*value, 1);
+            }
+        }
+    }
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get("a"), Some(&3));
+    assert_eq!(map.get("b"), Some(&2));
+    assert_eq!(map.get("c"), Some(&1));
+
+    let (key, value) = map
+        .raw_entry_mut()
+        .from_key("c")
+        .or_insert(String::from("c"), 0);
+    assert_eq!((key.as_str(), *value), ("c", 1));
+    let (key, value) = map
+        .raw_entry_mut()
+        .from_key("d")
+        .or_insert_with(|| (String::from("d"), 4));
+    assert_eq!((key.as_str(), *value), ("d", 4));
+    map.check_consistency();
+}
+
+#[test]
+fn test_debug_after_partial_consumption() {
+    fn check<I>(mut iter: I, mut expected: &[(i32, i32)], rng: &mut StdRng)
+    where
+        I: DoubleEndedIterator<Item = (i32, i32)> + core::fmt::Debug,
+    {
+        loop {
+            assert_eq!(format!("{:?}", iter), format!("{:?}", expected));
+            let item = if rng.gen() {
+                let item = iter.next();
+                if let Some((first, rest)) = expected.split_first() {
+                    assert_eq!(item, Some(*first));
+                    expected = rest;
+                }
+                item
+            } else {
+                let item = iter.next_back();
+                if let Some((last, rest)) = expected.split_last() {
+                    assert_eq!(item, Some(*last));
+                    expected = rest;
+                }
+                item
+            };
+            if item.is_none() {
+                assert!(expected.is_empty());
+                break;
+            }
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for len in [0, 1, 2, 3, 10, 100] {
+        let entries: Vec<_> = (0..len).map(|k| (k, -k)).collect();
+        let map: AvlTreeMap<_, _> = entries.iter().copied().collect();
+
+        check(map.clone().into_iter(), &entries, &mut rng);
+        let lo = (len / 4) as usize;
+        let hi = (len - len / 4) as usize;
+        check(
+            map.clone().into_range(lo as i32..hi as i32),
+            &entries[lo..hi],
+            &mut rng,
+        );
+
+        let mut drained = map.clone();
+        check(drained.drain(), &entries, &mut rng);
+        drained.check_consistency();
+
+        let mut mutable = map.clone();
+        let mut iter_mut = mutable.iter_mut();
+        let mut expected = &entries[..];
+        while !expected.is_empty() {
+            assert_eq!(format!("{:?}", iter_mut), format!("{:?}", expected));
+            if rng.gen() {
+                iter_mut.next();
+                expected = &expected[1..];
+            } else {
+                iter_mut.next_back();
+                expected = &expected[..expected.len() - 1];
+            }
+        }
+        assert_eq!(format!("{:?}", iter_mut), "[]");
+    }
+}
+
+#[test]
+fn test_reset_range_bounds() {
+    let map: AvlTreeMap<_, _> = (0..20).map(|k| (k * 2, k)).collect();
+
+    let mut range = map.range(..);
+    map.reset_range_start_bound_included(&mut range, &5);
+    map.reset_range_end_bound_included(&mut range, &15);
+    assert!(range.clone().map(|(k, _)| *k).eq([6, 8, 10, 12, 14]));
+    assert_eq!(range.next(), Some((&6, &3)));
+    assert_eq!(range.next_back(), Some((&14, &7)));
+
+    map.reset_range_start_bound_included(&mut range, &10);
+    map.reset_range_end_bound_included(&mut range, &12);
+    assert!(range.clone().map(|(k, _)| *k).eq([10, 12]));
+
+    map.reset_range_end_bound_included(&mut range, &11);
+    assert!(range.clone().map(|(k, _)| *k).eq([10]));
+
+    let mut range = map.range(10..=20);
+    map.reset_range_end_bound_included(&mut range, &-1);
+    assert_eq!(range.next(), None);
+    assert_eq!(range.next_back(), None);
+
+    let mut range = map.range(10..=20);
+    map.reset_range_start_bound_included(&mut range, &21);
+    assert_eq!(range.next(), None);
+    assert_eq!(range.next_back(), None);
+}
+
+#[test]
+fn test_iter_indexed() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen(), rng.gen())).collect();
+
+    let mut prev_key = None;
+    for (expected_index, (index, key, value)) in map.iter_indexed().enumerate() {
+        assert_eq!(index, expected_index);
+        assert!(prev_key < Some(key));
+        assert_eq!(map.get(key), Some(value));
+        prev_key = Some(key);
+    }
+    assert_eq!(map.iter_indexed().len(), map.len());
+
+    let mut iter = map.iter_indexed();
+    assert_eq!(
+        iter.next_back().map(|(index, _, _)| index),
+        Some(map.len() - 1)
+    );
+    assert_eq!(iter.next().map(|(index, _, _)| index), Some(0));
+    assert!(iter
+        .rev()
+        .map(|(index, _, _)| index)
+        .eq((1..map.len() - 1).rev()));
+
+    let map: AvlTreeMap<_, _> = [(3, 'c'), (1, 'a'), (2, 'b')].into_iter().collect();
+    assert_eq!(
+        format!("{:?}", map.iter_indexed()),
+        "[(0, 1, 'a'), (1, 2, 'b'), (2, 3, 'c')]"
+    );
+}
+
+#[test]
+fn test_set_contains_all() {
+    let set: AvlTreeSet<_> = (0..N).map(|value| value * 2).collect();
+    assert!(set.contains_all(&[]));
+    assert!(set.contains_all(&[0, 2, 2, 100, 2 * N - 2]));
+    assert!(set.contains_all(&set.iter().cloned().collect::<Vec<_>>()));
+    assert!(!set.contains_all(&[0, 2, 3]));
+    assert!(!set.contains_all(&[-2, 0]));
+    assert!(!set.contains_all(&[0, 2 * N]));
+    assert!(!AvlTreeSet::<i32>::new().contains_all(&[0]));
+
+    let set: AvlTreeSet<String> = ["a", "b", "c"].iter().map(|s| String::from(*s)).collect();
+    assert!(set.contains_all(["a", "c"]));
+    assert!(!set.contains_all(["a", "bb"]));
+}
+
+#[test]
+#[should_panic]
+#[cfg(debug_assertions)]
+fn test_set_contains_all_unsorted() {
+    let set: AvlTreeSet<_> = (0..N).collect();
+    set.contains_all(&[2, 1]);
+}
+
+#[test]
+fn test_index_of() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen_range(0..4 * N), 0)).collect();
+    for (index, key) in map.keys().enumerate() {
+        assert_eq!(map.index_of(key), Some(index));
+        assert_eq!(map.iter().nth(index).map(|(k, _)| k), Some(key));
+    }
+    for key in 0..4 * N {
+        if !map.contains_key(&key) {
+            assert_eq!(map.index_of(&key), None);
+        }
+    }
+    assert_eq!(AvlTreeMap::<i32, i32>::new().index_of(&0), None);
+}
+
+#[test]
+fn test_shrink_to_fit() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = AvlTreeMap::with_capacity(N as usize);
+    map.extend((0..N).map(|_| (rng.gen::<i32>(), rng.gen::<i32>())));
+    let capacity = map.capacity();
+    let mut kept: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+    kept.shuffle(&mut rng);
+    for (key, _) in kept.drain(N as usize / 10..) {
+        map.remove(&key);
+    }
+    assert_eq!(map.capacity(), capacity);
+
+    map.shrink_to_fit();
+    map.check_consistency();
+    assert_eq!(map.capacity(), map.len());
+    kept.sort_unstable();
+    assert!(map.iter().map(|(k, v)| (*k, *v)).eq(kept.iter().copied()));
+
+    // Inserts allocate again after shrinking
+    map.insert(i32::MAX, 0);
+    map.check_consistency();
+    assert_eq!(map.capacity(), map.len());
+    map.shrink_to_fit();
+    assert_eq!(map.capacity(), map.len());
+
+    let mut set = AvlTreeSet::with_capacity(10);
+    assert_eq!(set.capacity(), 10);
+    set.extend(0..5);
+    set.remove(&3);
+    assert_eq!(set.capacity(), 10);
+    set.shrink_to_fit();
+    set.check_consistency();
+    assert_eq!(set.capacity(), 4);
+    assert!(set.iter().eq([0, 1, 2, 4].iter()));
+}
+
+#[test]
+fn test_try_range() {
+    let map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key)).collect();
+    assert!(map.try_range(2..5).unwrap().map(|(k, _)| *k).eq(2..5));
+    assert!(map.try_range(5..5).unwrap().next().is_none());
+    assert!(map
+        .try_range((Bound::Included(5), Bound::Excluded(6)))
+        .is_ok());
+    assert_eq!(
+        map.try_range((Bound::Included(5), Bound::Excluded(4)))
+            .unwrap_err(),
+        RangeError::StartGreaterThanEnd
+    );
+    assert_eq!(
+        map.try_range((Bound::Excluded(5), Bound::Included(4)))
+            .unwrap_err(),
+        RangeError::StartGreaterThanEnd
+    );
+    assert_eq!(
+        map.try_range((Bound::Excluded(5), Bound::Excluded(5)))
+            .unwrap_err(),
+        RangeError::EmptyExcludedExcluded
+    );
+    assert_eq!(
+        format!("{}", RangeError::StartGreaterThanEnd),
+        "range start is greater than range end"
+    );
+
+    let set: AvlTreeSet<_> = (0..10).collect();
+    assert!(set.try_range(7..).unwrap().eq([7, 8, 9].iter()));
+    assert_eq!(
+        set.try_range((Bound::Included(3), Bound::Included(2)))
+            .unwrap_err(),
+        RangeError::StartGreaterThanEnd
+    );
+}
+
+#[test]
+#[should_panic(expected = "range start and end are equal and excluded")]
+fn test_range_panic() {
+    let map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key)).collect();
+    map.range((Bound::Excluded(5), Bound::Excluded(5)));
+}
+
+#[test]
+fn test_range_from_to() {
+    let map: AvlTreeMap<_, _> = (0..10).map(|key| (key * 2, key)).collect();
+    let keys = |range: super::map::Range<'_, i32, i32>| range.map(|(k, _)| *k).collect::<Vec<_>>();
+
+    assert_eq!(keys(map.range_from(Bound::Included(&14))), [14, 16, 18]);
+    assert_eq!(keys(map.range_from(Bound::Included(&13))), [14, 16, 18]);
+    assert_eq!(keys(map.range_from(Bound::Excluded(&14))), [16, 18]);
+    assert_eq!(keys(map.range_from(Bound::Excluded(&18))), []);
+    assert_eq!(keys(map.range_from(Bound::Unbounded)).len(), 10);
+
+    assert_eq!(keys(map.range_to(Bound::Included(&4))), [0, 2, 4]);
+    assert_eq!(keys(map.range_to(Bound::Included(&5))), [0, 2, 4]);
+    assert_eq!(keys(map.range_to(Bound::Excluded(&4))), [0, 2]);
+    assert_eq!(keys(map.range_to(Bound::Excluded(&0))), []);
+    assert_eq!(keys(map.range_to(Bound::Unbounded)).len(), 10);
+
+    let mut range = map.range_from(Bound::Excluded(&10));
+    assert_eq!(range.next_back(), Some((&18, &9)));
+    assert_eq!(range.next(), Some((&12, &6)));
+
+    let map: AvlTreeMap<String, ()> = ["a", "b", "c"]
+        .iter()
+        .map(|s| (String::from(*s), ()))
+        .collect();
+    assert_eq!(map.range_from(Bound::Excluded("a")).count(), 2);
+    assert_eq!(map.range_to(Bound::Excluded("a")).count(), 0);
+}
+
+#[test]
+fn test_drop_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    struct PanicOnDrop<'a> {
+        panic: bool,
+        drops: &'a Cell<usize>,
+    }
+
+    impl Drop for PanicOnDrop<'_> {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+            if self.panic {
+                panic!("panic on drop");
+            }
+        }
+    }
+
+    const LEN: usize = 100;
+    let drops = Cell::new(0);
+    let create_map = || {
+        (0..LEN)
+            .map(|key| {
+                let value = PanicOnDrop {
+                    panic: key == LEN / 3,
+                    drops: &drops,
+                };
+                (key, value)
+            })
+            .collect::<AvlTreeMap<_, _>>()
+    };
+
+    // Drop the map
+    let map = create_map();
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(map))).is_err());
+    assert_eq!(drops.replace(0), LEN);
+
+    // Clear the map
+    let mut map = create_map();
+    assert!(catch_unwind(AssertUnwindSafe(|| map.clear())).is_err());
+    assert!(map.is_empty());
+    map.check_consistency();
+    assert_eq!(drops.replace(0), LEN);
+    map.insert(
+        0,
+        PanicOnDrop {
+            panic: false,
+            drops: &drops,
+        },
+    );
+    drop(map);
+    assert_eq!(drops.replace(0), 1);
+
+    // Drop a partially consumed owning iterator
+    let mut into_iter = create_map().into_iter();
+    into_iter.next();
+    into_iter.next_back();
+    assert_eq!(drops.replace(0), 2);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(into_iter))).is_err());
+    assert_eq!(drops.replace(0), LEN - 2);
+
+    // Drop a partially consumed drain
+    let mut map = create_map();
+    let mut drain = map.drain();
+    drain.next_back();
+    assert_eq!(drops.replace(0), 1);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(drain))).is_err());
+    assert_eq!(drops.replace(0), LEN - 1);
+    assert!(map.is_empty());
+    map.check_consistency();
+
+    // Drop the elements outside of a range
+    let map = create_map();
+    assert!(catch_unwind(AssertUnwindSafe(|| map.into_range(LEN / 2..))).is_err());
+    assert_eq!(drops.replace(0), LEN);
+}
+
+#[test]
+fn test_range_resume() {
+    let mut map: AvlTreeMap<_, _> = (0..20).map(|key| (key * 2, key)).collect();
+
+    // Pause after processing some keys
+    let mut processed = Vec::new();
+    let mut iter = map.iter();
+    for (key, _) in iter.by_ref().take(5) {
+        processed.push(*key);
+    }
+    let next_key = iter.checkpoint().copied();
+    let last_key = *processed.last().unwrap();
+    assert_eq!(next_key, Some(10));
+
+    // Mutate the map
+    map.insert(9, 0);
+    map.insert(11, 0);
+    map.remove(&10);
+    map.remove(&12);
+
+    // Resume after the last processed key
+    let mut range = map.range_resume(&last_key);
+    assert_eq!(range.checkpoint(), Some(&9));
+    assert!(range.by_ref().take(3).map(|(k, _)| *k).eq([9, 11, 14]));
+    assert_eq!(range.checkpoint(), Some(&16));
+    assert_eq!(range.count(), 12);
+
+    // Resume at the checkpoint
+    let mut range = map.range_from(Bound::Included(&next_key.unwrap()));
+    assert_eq!(range.next(), Some((&11, &0)));
+    assert!(range.map(|(k, _)| *k).eq((14..40).step_by(2)));
+
+    let mut range = map.range_resume(&38);
+    assert_eq!(range.checkpoint(), None);
+    assert_eq!(range.next(), None);
+}
+
+#[test]
+fn test_entry_or_insert_with_kv() {
+    let mut map: AvlTreeMap<String, Vec<i32>> = AvlTreeMap::new();
+
+    let (key, value) = map.entry(String::from("a")).or_insert_with_kv(Vec::new);
+    value.push(1);
+    let key_ptr: *const String = key;
+    assert_eq!(map.get_key_value("a"), Some((&String::from("a"), &vec![1])));
+    assert!(core::ptr::eq(map.get_key_value("a").unwrap().0, key_ptr));
+
+    let (key, value) = map
+        .entry(String::from("a"))
+        .or_insert_with_kv(|| unreachable!());
+    value.push(2);
+    assert!(core::ptr::eq(key, key_ptr));
+    assert_eq!(map["a"], [1, 2]);
+    map.check_consistency();
+}
+
+#[cfg(feature = "multimap")]
+#[test]
+fn test_multimap() {
+    let mut map: AvlTreeMap<&str, Vec<i32>> = AvlTreeMap::new();
+    for (key, value) in [("b", 1), ("a", 2), ("b", 3), ("c", 4), ("b", 5), ("a", 6)] {
+        map.push(key, value);
+    }
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get_all("a"), [2, 6]);
+    assert_eq!(map.get_all("b"), [1, 3, 5]);
+    assert_eq!(map.get_all("c"), [4]);
+    assert!(map.get_all("d").is_empty());
+    assert!(map.keys().eq(["a", "b", "c"].iter()));
+    map.check_consistency();
+}
+
+#[test]
+fn test_sum_map() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = AvlTreeSumMap::new();
+    let mut expected = AvlTreeMap::new();
+    assert_eq!(map.sum(), 0);
+    assert_eq!(map.range_sum(..), 0);
+
+    // Inserts, overwrites and removes keep the sums of all sub trees up to date
+    for i in 0..10 * N {
+        let key = rng.gen_range(0..N);
+        if rng.gen_bool(0.4) {
+            assert_eq!(map.remove(&key), expected.remove(&key));
+        } else {
+            let value = rng.gen_range(-100..100);
+            assert_eq!(map.insert(key, value), expected.insert(key, value));
+        }
+        if i % 100 == 0 {
+            map.check_consistency();
+        }
+    }
+    map.check_consistency();
+    assert_eq!(map.len(), expected.len());
+    assert!(map.iter().eq(expected.iter()));
+    assert_eq!(map.sum(), expected.values().sum());
+
+    // Range sums match iterating and summing
+    let iter_sum = |range: (Bound<i32>, Bound<i32>)| expected.range(range).map(|(_, v)| v).sum();
+    for _ in 0..N {
+        let a = rng.gen_range(-1..=N);
+        let b = rng.gen_range(a..=N);
+        for range in [
+            (Bound::Included(a), Bound::Excluded(b)),
+            (Bound::Included(a), Bound::Included(b)),
+            (Bound::Excluded(a), Bound::Included(b)),
+            (Bound::Unbounded, Bound::Excluded(b)),
+            (Bound::Excluded(a), Bound::Unbounded),
+        ] {
+            assert_eq!(map.range_sum(range), iter_sum(range));
+        }
+    }
+    assert_eq!(map.range_sum(N..), 0);
+    assert_eq!(map.range_sum(..0), 0);
+
+    // Sums wrap around on overflow
+    let mut map: AvlTreeSumMap<_> = (0..4).map(|key| (key, i64::MAX)).collect();
+    map.check_consistency();
+    assert_eq!(map.sum(), i64::MAX.wrapping_mul(4));
+    map.insert(3, 4);
+    assert_eq!(map.range_sum(2..), i64::MAX.wrapping_add(4));
+    let clone = map.clone();
+    clone.check_consistency();
+    assert_eq!(format!("{:?}", clone), format!("{:?}", map));
+    map.clear();
+    assert_eq!(map.sum(), 0);
+}
+
+#[test]
+#[should_panic(expected = "range start and end are equal and excluded")]
+fn test_sum_map_range_panic() {
+    let map: AvlTreeSumMap<_> = (0..10).map(|key| (key, key)).collect();
+    map.range_sum((Bound::Excluded(5), Bound::Excluded(5)));
+}
+
+#[test]
+fn test_set_replace() {
+    #[derive(Debug, Clone)]
+    struct Tagged {
+        id: i32,
+        tag: u32,
+    }
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+    impl Eq for Tagged {}
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Tagged {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    let mut set: AvlTreeSet<_> = (0..N).map(|id| Tagged { id, tag: 0 }).collect();
+    let before = set.clone();
+    for id in (0..N).step_by(7) {
+        let old = set.replace(Tagged { id, tag: 1 }).unwrap();
+        assert_eq!((old.id, old.tag), (id, 0));
+    }
+    set.check_consistency();
+    assert!(set.same_shape(&before));
+    for value in &set {
+        assert_eq!(value.tag, u32::from(value.id % 7 == 0));
+    }
+
+    assert!(set.replace(Tagged { id: N, tag: 2 }).is_none());
+    set.check_consistency();
+    assert_eq!(set.len(), N as usize + 1);
+    assert_eq!(set.last().map(|value| value.tag), Some(2));
+}
+
+#[test]
+fn test_is_consistent() {
+    #[derive(Debug)]
+    struct Key(Cell<i32>);
+    impl PartialEq for Key {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.get() == other.0.get()
+        }
+    }
+    impl Eq for Key {}
+    impl PartialOrd for Key {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Key {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.0.get().cmp(&other.0.get())
+        }
+    }
+
+    let set: AvlTreeSet<_> = (0..N).map(|key| Key(Cell::new(key))).collect();
+    assert!(set.is_consistent());
+    assert!(AvlTreeSet::<Key>::new().is_consistent());
+
+    // Break the order between a node and a descendant that is not its child
+    let first = set.first().unwrap();
+    first.0.set(N / 2);
+    assert!(!set.is_consistent());
+    first.0.set(0);
+    assert!(set.is_consistent());
+
+    // Break the order of two adjacent keys
+    let mut iter = set.iter();
+    let a = iter.nth(10).unwrap();
+    let b = iter.next().unwrap();
+    a.0.swap(&b.0);
+    assert!(!set.is_consistent());
+    a.0.swap(&b.0);
+    assert!(set.is_consistent());
+
+    // Duplicate keys
+    set.last().unwrap().0.set(N - 2);
+    assert!(!set.is_consistent());
+}
+
+#[test]
+fn test_iter_last_min_max() {
+    struct Key<'a> {
+        key: i32,
+        comparisons: &'a Cell<usize>,
+    }
+    impl PartialEq for Key<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            self.comparisons.set(self.comparisons.get() + 1);
+            self.key == other.key
+        }
+    }
+    impl Eq for Key<'_> {}
+    impl PartialOrd for Key<'_> {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Key<'_> {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.comparisons.set(self.comparisons.get() + 1);
+            self.key.cmp(&other.key)
+        }
+    }
+
+    let comparisons = Cell::new(0);
+    let map: AvlTreeMap<_, _> = (0..N)
+        .map(|key| {
+            let value = N - key;
+            let key = Key {
+                key,
+                comparisons: &comparisons,
+            };
+            (key, value)
+        })
+        .collect();
+    let set: AvlTreeSet<_> = (0..N).map(|key| key * 3).collect();
+
+    comparisons.set(0);
+    assert_eq!(map.keys().min().map(|k| k.key), Some(0));
+    assert_eq!(map.keys().max().map(|k| k.key), Some(N - 1));
+    assert_eq!(map.iter().min().map(|(k, _)| k.key), Some(0));
+    assert_eq!(map.iter().max().map(|(k, _)| k.key), Some(N - 1));
+    assert_eq!(comparisons.get(), 0);
+
+    let mut iter = map.keys();
+    iter.next();
+    iter.next_back();
+    assert_eq!(iter.clone().min().map(|k| k.key), Some(1));
+    assert_eq!(iter.max().map(|k| k.key), Some(N - 2));
+    assert_eq!(
+        map.iter().last().map(|(k, v)| (k.key, *v)),
+        Some((N - 1, 1))
+    );
+    assert_eq!(map.values().last(), Some(&1));
+    assert_eq!(map.values().max(), Some(&N));
+    assert_eq!(map.values().min(), Some(&1));
+
+    let keys: Vec<_> = map.keys().map(|k| k.key).collect();
+    assert_eq!(keys.iter().min(), Some(&0));
+    assert_eq!(keys.iter().last(), Some(&(N - 1)));
+
+    assert_eq!(set.range(10..20).min(), Some(&12));
+    assert_eq!(set.range(10..20).max(), Some(&18));
+    assert_eq!(set.range(10..20).last(), Some(&18));
+    assert_eq!(set.iter().min(), Some(&0));
+    assert_eq!(set.iter().max(), Some(&(3 * N - 3)));
+    assert_eq!(set.range(10..12).max(), None);
+    assert_eq!(AvlTreeSet::<i32>::new().iter().last(), None);
+}
+
+#[test]
+fn test_split_at() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen_range(0..N), rng.gen())).collect();
+    for key in [-1, 0, 1, N / 3, N / 2, N - 1, N] {
+        let (less, greater_or_equal) = map.clone().split_at(&key);
+        less.check_consistency();
+        greater_or_equal.check_consistency();
+        assert!(less.keys().all(|k| *k < key));
+        assert!(greater_or_equal.keys().all(|k| *k >= key));
+        assert_eq!(less.len() + greater_or_equal.len(), map.len());
+        assert!(less.iter().chain(greater_or_equal.iter()).eq(map.iter()));
+    }
+
+    let (less, greater_or_equal) = map.clone().split_at(&-1);
+    assert!(less.is_empty());
+    assert_eq!(greater_or_equal, map);
+    let (less, greater_or_equal) = map.clone().split_at(&N);
+    assert_eq!(less, map);
+    assert!(greater_or_equal.is_empty());
+}
+
+#[test]
+fn test_set_runs() {
+    let set: AvlTreeSet<i32> = [1, 2, 3, 7, 8, 10, 12, 13, -5, -4, 20]
+        .into_iter()
+        .collect();
+    let runs: Vec<_> = set
+        .runs(|value| value + 1)
+        .map(|(first, last)| (*first, *last))
+        .collect();
+    assert_eq!(
+        runs,
+        [(-5, -4), (1, 3), (7, 8), (10, 10), (12, 13), (20, 20)]
+    );
+
+    let runs: Vec<_> = set
+        .runs(|value| value + 2)
+        .map(|(first, last)| (*first, *last))
+        .collect();
+    assert_eq!(
+        runs,
+        [
+            (-5, -5),
+            (-4, -4),
+            (1, 1),
+            (2, 2),
+            (3, 3),
+            (7, 7),
+            (8, 12),
+            (13, 13),
+            (20, 20)
+        ]
+    );
+
+    let set: AvlTreeSet<i32> = (0..N).collect();
+    assert!(set
+        .runs(|value| value + 1)
+        .map(|(first, last)| (*first, *last))
+        .eq([(0, N - 1)]));
+    assert_eq!(
+        AvlTreeSet::<i32>::new().runs(|value| value + 1).next(),
+        None
+    );
+}
+
+#[cfg(feature = "rotation_observer")]
+#[test]
+fn test_rotation_observer() {
+    use super::map::RotationDirection::{Left, Right};
+    use alloc::boxed::Box;
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut map = AvlTreeMap::new();
+    let recorder = Arc::clone(&events);
+    map.set_rotation_observer(Box::new(move |event| {
+        recorder.lock().unwrap().push((*event.key, event.direction));
+    }));
+    let take_events = || core::mem::take(&mut *events.lock().unwrap());
+
+    // Sorted inserts cause single rotations
+    for key in 1..=7 {
+        map.insert(key, ());
+    }
+    assert_eq!(take_events(), [(1, Left), (3, Left), (2, Left), (5, Left)]);
+
+    // Left-right case causes a double rotation
+    map.clear();
+    map.insert(30, ());
+    map.insert(10, ());
+    map.insert(20, ());
+    assert_eq!(take_events(), [(10, Left), (30, Right)]);
+
+    // Balancing is not affected by the observer
+    let mut rng = StdRng::seed_from_u64(0);
+    let keys: Vec<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+    let mut plain = AvlTreeMap::new();
+    map.clear();
+    for key in &keys {
+        map.insert(*key, ());
+        plain.insert(*key, ());
+    }
+    for key in keys.iter().step_by(3) {
+        map.remove(key);
+        plain.remove(key);
+    }
+    map.check_consistency();
+    assert!(map.same_shape(&plain));
+    assert!(!take_events().is_empty());
+
+    // The observer is not cloned with the map
+    let mut cloned = map.clone();
+    for key in N..2 * N {
+        cloned.insert(key, ());
+    }
+    assert!(take_events().is_empty());
+}
+
+#[test]
+fn test_extend_sorted_greater() {
+    let mut map = AvlTreeMap::new();
+    let mut expected = Vec::new();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut next_key = 0;
+    for _ in 0..100 {
+        let len = rng.gen_range(0..50);
+        let batch: Vec<_> = (0..len)
+            .map(|_| {
+                next_key += rng.gen_range(1..10);
+                (next_key, rng.gen::<i32>())
+            })
+            .collect();
+        expected.extend(batch.iter().copied());
+        map.extend_sorted_greater(batch);
+        map.check_consistency();
+        assert_eq!(map.len(), expected.len());
+    }
+    assert!(map
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .eq(expected.iter().copied()));
+
+    let mut map: AvlTreeMap<_, _> = (0..N).map(|key| (key, key)).collect();
+    map.extend_sorted_greater((N..N + 1).map(|key| (key, key)));
+    map.extend_sorted_greater((N + 1..3 * N).map(|key| (key, key)));
+    map.check_consistency();
+    assert!(map
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .eq((0..3 * N).map(|key| (key, key))));
+}
+
+#[test]
+#[should_panic(expected = "keys are not greater than the last key of the map")]
+#[cfg(debug_assertions)]
+fn test_extend_sorted_greater_not_greater() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key)).collect();
+    map.extend_sorted_greater([(9, 9), (10, 10)]);
+}
+
+#[test]
+fn test_range_owned() {
+    let map: AvlTreeMap<String, usize> = ["apple", "banana", "cherry", "date", "fig"]
+        .iter()
+        .enumerate()
+        .map(|(index, key)| (String::from(*key), index))
+        .collect();
+    let values =
+        |range: super::map::Range<'_, String, usize>| range.map(|(_, v)| *v).collect::<Vec<_>>();
+
+    let start = String::from("b");
+    let end = String::from("d");
+    assert_eq!(values(map.range_owned(start.clone()..end.clone())), [1, 2]);
+    assert_eq!(values(map.range_owned(start.clone()..)), [1, 2, 3, 4]);
+    assert_eq!(
+        values(map.range_owned(..=String::from("date"))),
+        [0, 1, 2, 3]
+    );
+    assert_eq!(
+        values(map.range_owned((Bound::Excluded(String::from("date")), Bound::Unbounded))),
+        [4]
+    );
+    assert_eq!(values(map.range_owned(&start..&end)), [1, 2]);
+}
+
+#[test]
+fn test_from_iter_first() {
+    let map = AvlTreeMap::from_iter_first([(1, "a"), (1, "b")]);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get(&1), Some(&"a"));
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let entries: Vec<(i32, i32)> = (0..N)
+        .map(|_| (rng.gen_range(0..N / 4), rng.gen()))
+        .collect();
+    let map = AvlTreeMap::from_iter_first(entries.iter().copied());
+    map.check_consistency();
+    let last_wins: AvlTreeMap<_, _> = entries.iter().rev().copied().collect();
+    assert_eq!(map, last_wins);
+}
+
+#[test]
+fn test_find_by_value() {
+    let map: AvlTreeMap<_, _> = [(3, 'c'), (1, 'a'), (2, 'b'), (4, 'b')]
+        .into_iter()
+        .collect();
+    assert!(map.contains_value(&'a'));
+    assert!(!map.contains_value(&'z'));
+    assert_eq!(map.find_by_value(&'b'), Some(&2));
+    assert_eq!(map.find_by_value(&'c'), Some(&3));
+    assert_eq!(map.find_by_value(&'z'), None);
+    assert!(!AvlTreeMap::<i32, char>::new().contains_value(&'a'));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_extend() {
+    use rayon::prelude::*;
+
+    let key = |index: i32| index.wrapping_mul(7919) % (4 * N);
+    let map: AvlTreeMap<i32, i32> = (0..10 * N)
+        .into_par_iter()
+        .map(|index| (key(index), index))
+        .collect();
+    map.check_consistency();
+    let sequential: AvlTreeMap<i32, i32> = (0..10 * N).map(|index| (key(index), index)).collect();
+    assert_eq!(map, sequential);
+
+    // Small and large batches, later entries overwrite earlier ones
+    let mut map = sequential.clone();
+    let mut expected = sequential;
+    for range in [0..10, 0..N, -N..5 * N] {
+        map.par_extend(range.clone().into_par_iter().map(|key| (key, -key)));
+        expected.extend(range.map(|key| (key, -key)));
+        map.check_consistency();
+        assert_eq!(map, expected);
+    }
+}
+
+#[test]
+fn test_entry_remove_if() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key * 10)).collect();
+    for key in 0..12 {
+        let removed = map.entry(key).remove_if(|value| {
+            *value += 1;
+            *value > 51
+        });
+        assert_eq!(
+            removed,
+            if (6..10).contains(&key) {
+                Some(key * 10 + 1)
+            } else {
+                None
+            }
+        );
+    }
+    map.check_consistency();
+    assert!(map
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .eq((0..6).map(|key| (key, key * 10 + 1))));
+}
+
+#[test]
+fn test_occupied_entry_remove_entry_if() {
+    let mut map: AvlTreeMap<_, _> = (0..10).map(|key| (key, key * 10)).collect();
+    let entry = match map.entry(4) {
+        Entry::Occupied(entry) => entry,
+        Entry::Vacant(_) => unreachable!(),
+    };
+
+    // Predicate fails, the entry is handed back and can still be used
+    let mut entry = entry
+        .remove_entry_if(|key, value| *key == 5 || *value > 40)
+        .unwrap_err();
+    *entry.get_mut() += 1;
+    let entry = entry.next_entry().unwrap();
+
+    // Predicate holds, the entry is removed
+    assert_eq!(
+        entry.remove_entry_if(|key, _| *key == 5).ok(),
+        Some((5, 50))
+    );
+    map.check_consistency();
+    assert_eq!(map.get(&4), Some(&41));
+    assert!(!map.contains_key(&5));
+    assert_eq!(map.len(), 9);
+}
+
+#[test]
+fn test_set_relations_crate_root() {
+    use crate::AvlTreeSet;
+
+    let all: AvlTreeSet<_> = (0..100).collect();
+    let even: AvlTreeSet<_> = (0..100).step_by(2).collect();
+    let odd: AvlTreeSet<_> = (1..100).step_by(2).collect();
+    let overlapping: AvlTreeSet<_> = (50..150).collect();
+    let empty = AvlTreeSet::new();
+
+    // Subset and superset
+    assert!(even.is_subset(&all) && all.is_superset(&even));
+    assert!(!all.is_subset(&even) && !even.is_superset(&all));
+    assert!(empty.is_subset(&even) && even.is_superset(&empty));
+
+    // Disjoint
+    assert!(even.is_disjoint(&odd) && odd.is_disjoint(&even));
+    assert!(empty.is_disjoint(&all));
+    assert!(!even.is_disjoint(&all));
+
+    // Overlapping
+    assert!(!overlapping.is_subset(&all) && !overlapping.is_superset(&all));
+    assert!(!overlapping.is_disjoint(&all) && !overlapping.is_disjoint(&odd));
+    assert_eq!(even.union(&odd).cloned().collect::<AvlTreeSet<_>>(), all);
+}
+
+#[test]
+fn test_stats() {
+    use super::map::TreeStats;
+
+    assert_eq!(
+        AvlTreeMap::<i32, ()>::new().stats(),
+        TreeStats {
+            within_avl_bound: true,
+            ..TreeStats::default()
+        }
+    );
+
+    let map: AvlTreeMap<_, _> = (0..7).map(|key| (key, ())).collect();
+    assert_eq!(
+        map.stats(),
+        TreeStats {
+            len: 7,
+            height: 3,
+            min_depth: 3,
+            within_avl_bound: true
+        }
+    );
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = AvlTreeMap::new();
+    for _ in 0..10_000 {
+        map.insert(rng.gen::<u32>(), ());
+    }
+    let stats = map.stats();
+    assert_eq!(stats.len, map.len());
+    assert!(stats.within_avl_bound);
+    // 1.44 * log2(10_002) is less than 20
+    assert!(stats.height < 20);
+    assert!(stats.min_depth <= stats.height);
+    assert!(stats.min_depth >= stats.height / 2);
+}
+
+#[test]
+fn test_seek_forward() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen_range(0..4 * N), 0)).collect();
+    let keys: Vec<i32> = map.keys().copied().collect();
+
+    for _ in 0..100 {
+        let start = rng.gen_range(0..4 * N);
+        let end = rng.gen_range(start..=4 * N);
+        let mut range = map.range(start..end);
+        let mut expected: Vec<i32> = keys
+            .iter()
+            .copied()
+            .filter(|k| (start..end).contains(k))
+            .collect();
+        while !expected.is_empty() {
+            let target = rng.gen_range(start - 10..end + 10);
+            range.seek_forward(&target);
+            expected.retain(|k| *k >= target);
+            assert_eq!(range.clone().map(|(k, _)| *k).collect::<Vec<_>>(), expected);
+            if range.next().is_some() {
+                expected.remove(0);
+            }
+        }
+        assert_eq!(range.next(), None);
+    }
+
+    let mut iter = map.iter();
+    iter.seek_forward(&(2 * N));
+    let first = keys.iter().find(|k| **k >= 2 * N).unwrap();
+    assert_eq!(iter.next().map(|(k, _)| k), Some(first));
+    assert_eq!(iter.next_back().map(|(k, _)| k), keys.last());
+    iter.seek_forward(&(4 * N));
+    assert_eq!(iter.next(), None);
+
+    let set: AvlTreeSet<_> = (0..100).step_by(5).collect();
+    let mut range = set.range(10..=50);
+    range.seek_forward(&22);
+    assert!(range.clone().eq([25, 30, 35, 40, 45, 50].iter()));
+    range.seek_forward(&0);
+    assert_eq!(range.next(), Some(&25));
+    let mut iter = set.iter();
+    iter.seek_forward(&94);
+    assert!(iter.eq([95].iter()));
+}
+
+#[test]
+fn test_empty_iter_size_hint() {
+    let empty = (0, Some(0));
+    let mut map: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    assert_eq!(map.iter().size_hint(), empty);
+    assert_eq!(map.iter().next(), None);
+    assert_eq!(map.keys().size_hint(), empty);
+    assert_eq!(map.keys().next(), None);
+    assert_eq!(map.values().size_hint(), empty);
+    assert_eq!(map.values().next(), None);
+    assert_eq!(map.iter_mut().size_hint(), empty);
+    assert_eq!(map.range(..).size_hint(), empty);
+    assert_eq!(map.range(..).next(), None);
+    assert_eq!(map.drain().size_hint(), empty);
+    assert_eq!(map.clone().into_iter().size_hint(), empty);
+    assert_eq!(map.clone().into_iter().next(), None);
+
+    // Empty computed ranges on a non-empty map
+    map.extend((0..N).map(|i| (2 * i, i)));
+    assert_eq!(map.range(1..2).size_hint(), empty);
+    assert_eq!(map.range(1..2).next(), None);
+    assert_eq!(map.range(2 * N..).size_hint(), empty);
+    assert_eq!(map.range(..0).next_back(), None);
+    assert_eq!(map.range_mut(3..4).size_hint(), empty);
+    assert_eq!(map.clone().into_range(5..6).size_hint(), empty);
+
+    // Non-empty ranges report at least one element, and become empty when exhausted
+    let mut iter = map.range(0..3);
+    assert_eq!(iter.size_hint(), (1, None));
+    assert_eq!(iter.by_ref().count(), 2);
+    assert_eq!(iter.size_hint(), empty);
+    let mut into_iter = map.clone().into_iter();
+    into_iter.by_ref().for_each(drop);
+    assert_eq!(into_iter.size_hint(), empty);
+
+    let set: AvlTreeSet<i32> = AvlTreeSet::new();
+    assert_eq!(set.iter().size_hint(), empty);
+    assert_eq!(set.range(..).size_hint(), empty);
+    assert_eq!(set.clone().into_iter().size_hint(), empty);
+    assert_eq!(set.into_iter().next(), None);
+}
+
+#[test]
+fn test_iter_size_hint() {
+    let len = N as usize;
+    let exact = |len| (len, Some(len));
+    let mut map: AvlTreeMap<_, _> = (0..N).map(|i| (i, i)).collect();
+    assert_eq!(map.iter().size_hint(), exact(len));
+    assert_eq!(map.keys().size_hint(), exact(len));
+    assert_eq!(map.values().size_hint(), exact(len));
+    assert_eq!(map.iter_with_depth().size_hint(), exact(len));
+    assert_eq!(map.iter_mut().size_hint(), exact(len));
+    assert_eq!(map.values_mut().size_hint(), exact(len));
+
+    // Iterating from both ends keeps the length exact
+    let mut iter = map.iter();
+    iter.next();
+    iter.next_back();
+    assert_eq!(iter.size_hint(), exact(len - 2));
+    assert_eq!(iter.clone().size_hint(), exact(len - 2));
+    assert_eq!(iter.count(), len - 2);
+    let mut iter_mut = map.iter_mut();
+    iter_mut.nth(10);
+    assert_eq!(iter_mut.reborrow().size_hint(), exact(len - 11));
+
+    let mut into_iter = map.clone().into_iter();
+    assert_eq!(into_iter.size_hint(), exact(len));
+    into_iter.next_back();
+    assert_eq!(into_iter.size_hint(), exact(len - 1));
+    let mut drain = map.drain();
+    drain.next();
+    assert_eq!(drain.size_hint(), exact(len - 1));
+    drop(drain);
+
+    let set: AvlTreeSet<_> = (0..N).collect();
+    assert_eq!(set.iter().size_hint(), exact(len));
+    assert_eq!(set.iter().rev().skip(3).size_hint(), exact(len - 3));
+    assert_eq!(set.into_iter().size_hint(), exact(len));
+}
+
+#[test]
+fn test_pop_first_last_if() {
+    let mut map: AvlTreeMap<i32, String> = AvlTreeMap::new();
+    assert_eq!(map.pop_first_if(|_, _| true), None);
+    assert_eq!(map.pop_last_if(|_, _| true), None);
+
+    map.extend((0..N).map(|key| (key, format!("{}", key))));
+    let before = map.clone();
+
+    // Predicate fails, the map is left untouched
+    assert_eq!(map.pop_first_if(|key, _| *key > 0), None);
+    assert_eq!(map.pop_last_if(|_, value| value.is_empty()), None);
+    assert_eq!(map, before);
+
+    // Predicate passes, the extreme entry is removed
+    assert_eq!(
+        map.pop_first_if(|key, _| *key == 0),
+        Some((0, String::from("0")))
+    );
+    let last = N - 1;
+    assert_eq!(
+        map.pop_last_if(|key, _| *key == last),
+        Some((last, format!("{}", last)))
+    );
+    map.check_consistency();
+    assert_eq!(map.len(), before.len() - 2);
+
+    // Pop expired deadlines only
+    let now = 100;
+    let mut expired = 0;
+    while map.pop_first_if(|deadline, _| *deadline < now).is_some() {
+        expired += 1;
+    }
+    assert_eq!(expired, 99);
+    assert_eq!(map.first_key_value().map(|(key, _)| *key), Some(now));
+    map.check_consistency();
+}
+
+#[test]
+fn test_merge_join() {
+    let lhs: AvlTreeMap<i32, char> = [(1, 'a'), (3, 'b'), (5, 'c'), (7, 'd')]
+        .into_iter()
+        .collect();
+    let rhs: AvlTreeMap<i32, char> = [(2, 'x'), (3, 'y'), (7, 'z'), (9, 'w')]
+        .into_iter()
+        .collect();
+
+    let merged: Vec<_> = lhs.merge_join(&rhs).collect();
+    assert_eq!(
+        merged,
+        [
+            (&1, MergeSide::Left(&'a')),
+            (&2, MergeSide::Right(&'x')),
+            (&3, MergeSide::Both(&'b', &'y')),
+            (&5, MergeSide::Left(&'c')),
+            (&7, MergeSide::Both(&'d', &'z')),
+            (&9, MergeSide::Right(&'w')),
+        ]
+    );
+    let mut reversed: Vec<_> = lhs.merge_join(&rhs).rev().collect();
+    reversed.reverse();
+    assert_eq!(reversed, merged);
+    assert_eq!(
+        format!("{:?}", lhs.merge_join(&rhs).take(1).collect::<Vec<_>>()),
+        "[(1, Left('a'))]"
+    );
+
+    // Disjoint and empty maps
+    let high: AvlTreeMap<i32, char> = [(10, 'p'), (11, 'q')].into_iter().collect();
+    assert!(lhs
+        .merge_join(&high)
+        .map(|(key, _)| *key)
+        .eq([1, 3, 5, 7, 10, 11]));
+    assert!(high
+        .merge_join(&lhs)
+        .all(|(key, side)| matches!(side, MergeSide::Left(_)) == (*key >= 10)));
+    let empty = AvlTreeMap::new();
+    assert!(lhs
+        .merge_join(&empty)
+        .all(|(_, side)| matches!(side, MergeSide::Left(_))));
+    assert_eq!(empty.merge_join(&empty).next(), None);
+
+    // Diffing random maps matches lookups
+    let mut rng = StdRng::seed_from_u64(0);
+    let lhs: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen_range(0..N), 0)).collect();
+    let rhs: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen_range(0..N), 1)).collect();
+    let mut count = 0;
+    for (key, side) in lhs.merge_join(&rhs) {
+        let expected = match (lhs.get(key), rhs.get(key)) {
+            (Some(l), None) => MergeSide::Left(l),
+            (None, Some(r)) => MergeSide::Right(r),
+            (Some(l), Some(r)) => MergeSide::Both(l, r),
+            (None, None) => unreachable!(),
+        };
+        assert_eq!(side, expected);
+        count += 1;
+    }
+    assert_eq!(
+        count,
+        lhs.keys()
+            .chain(rhs.keys())
+            .collect::<AvlTreeSet<_>>()
+            .len()
+    );
+}
+
+#[test]
+fn test_replace_key_in_place() {
+    // Keys are ordered by id only, the label is a payload
+    #[derive(Debug)]
+    struct Key {
+        id: i32,
+        label: String,
+    }
+    impl PartialEq for Key {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+    impl Eq for Key {}
+    impl PartialOrd for Key {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Key {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+    let key = |id: i32| Key {
+        id,
+        label: String::new(),
+    };
+
+    let mut map: AvlTreeMap<Key, i32> = (0..N).map(|id| (key(id), id)).collect();
+    assert!(map.replace_key_in_place(&key(42), |k| k.label = String::from("answer")));
+    assert!(!map.replace_key_in_place(&key(N), |_| unreachable!()));
+    map.check_consistency();
+    let (k, v) = map.get_key_value(&key(42)).unwrap();
+    assert_eq!((k.label.as_str(), *v), ("answer", 42));
+    assert!(map.keys().filter(|k| !k.label.is_empty()).eq([&key(42)]));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "key ordering changed in place")]
+fn test_replace_key_in_place_reorder() {
+    let mut map: AvlTreeMap<i32, ()> = (0..10).map(|key| (key, ())).collect();
+    map.replace_key_in_place(&3, |key| *key = 7);
+}
+
+#[test]
+fn test_reborrow_mut_iter() {
+    let mut map: AvlTreeMap<i32, i32> = (0..5).map(|key| (key, key * 10)).collect();
+
+    let mut iter = map.iter_mut();
+    let (_, first) = iter.next().unwrap();
+    *first += 1;
+    iter.next_back();
+    assert_eq!(
+        format!("{:?}", iter.reborrow()),
+        "[(1, 10), (2, 20), (3, 30)]"
+    );
+    assert_eq!(iter.reborrow().count(), 3);
+    // Reborrowing does not advance the mutable iterator
+    for (_, value) in iter.by_ref() {
+        *value += 1;
+    }
+    assert_eq!(iter.reborrow().next(), None);
+    assert!(map.values().eq(&[1, 11, 21, 31, 40]));
+
+    let mut range = map.range_mut(1..4);
+    range.next();
+    assert!(range.reborrow().map(|(key, _)| *key).eq([2, 3]));
+    assert_eq!(format!("{:?}", range), "[(2, 21), (3, 31)]");
+
+    let mut values = map.values_mut();
+    values.next();
+    assert!(values.reborrow().eq(&[11, 21, 31, 40]));
+    assert_eq!(format!("{:?}", values), "[11, 21, 31, 40]");
+}
+
+#[test]
+fn test_set_from_sorted_iter() {
+    let set = AvlTreeSet::from_sorted_iter(0..10_000);
+    set.check_consistency();
+    assert_eq!(set.len(), 10_000);
+    assert!(set.iter().copied().eq(0..10_000));
+    assert!((0..10_000).all(|value| set.contains(&value)));
+    assert!(!set.contains(&-1) && !set.contains(&10_000));
+
+    let empty: AvlTreeSet<i32> = AvlTreeSet::from_sorted_iter(None);
+    assert!(empty.is_empty());
+
+    let strings = AvlTreeSet::from_sorted_iter(["a", "b", "c"].map(String::from));
+    assert!(strings.iter().eq(["a", "b", "c"]));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "keys are not in strictly ascending order")]
+fn test_set_from_sorted_iter_unsorted() {
+    AvlTreeSet::from_sorted_iter([1, 3, 2]);
+}
+
+#[test]
+fn test_iter_nth() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|_| (rng.gen(), rng.gen())).collect();
+    let sorted: Vec<(&i32, &i32)> = map.iter().collect();
+
+    for k in [0, 1, 17, sorted.len() / 2, sorted.len() - 1, sorted.len()] {
+        assert_eq!(map.iter().nth(k), sorted.get(k).copied());
+        assert_eq!(map.keys().nth(k), sorted.get(k).map(|(key, _)| *key));
+        assert_eq!(map.values().nth(k), sorted.get(k).map(|(_, value)| *value));
+        assert_eq!(map.range(..).nth(k), sorted.get(k).copied());
+        assert_eq!(map.iter().rev().nth(k), sorted.iter().rev().nth(k).copied());
+    }
+
+    // Skipping consumes the iterator up to the k-th element
+    let mut iter = map.iter();
+    assert_eq!(iter.nth(10), Some(sorted[10]));
+    assert_eq!(iter.next(), Some(sorted[11]));
+    let (start, _) = sorted[100];
+    let mut range = map.range(start..);
+    assert_eq!(range.nth(5), Some(sorted[105]));
+    assert_eq!(range.next_back(), sorted.last().copied());
+}
+
+#[test]
+fn test_iter_rev() {
+    use core::iter::Rev;
+
+    let map: AvlTreeMap<i32, i32> = (0..N).map(|key| (key, -key)).collect();
+    assert!(map.iter_rev().map(|(key, _)| *key).eq((0..N).rev()));
+    assert!(map.keys_rev().copied().eq((0..N).rev()));
+    assert!(map.values_rev().copied().eq((0..N).rev().map(|key| -key)));
+    assert!(map
+        .range_rev(10..20)
+        .map(|(key, _)| *key)
+        .eq((10..20).rev()));
+    assert_eq!(map.range_rev(..0).next(), None);
+
+    // Named types can be stored in struct fields
+    struct Cursor<'a> {
+        keys: Rev<super::map::Keys<'a, i32, i32>>,
+    }
+    let mut cursor = Cursor {
+        keys: map.keys_rev(),
+    };
+    assert_eq!(cursor.keys.next(), Some(&(N - 1)));
+    assert_eq!(cursor.keys.next_back(), Some(&0));
+
+    let empty: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    assert_eq!(empty.iter_rev().next(), None);
+}
+
+#[test]
+fn test_nearest() {
+    let map: AvlTreeMap<i32, char> = [(10, 'a'), (20, 'b'), (40, 'c')].into_iter().collect();
+    let dist = |query: &i32, key: &i32| query.abs_diff(*key);
+
+    // Without a distance the floor is picked
+    assert_eq!(map.nearest(&5), Some((&10, &'a')));
+    assert_eq!(map.nearest(&10), Some((&10, &'a')));
+    assert_eq!(map.nearest(&19), Some((&10, &'a')));
+    assert_eq!(map.nearest(&50), Some((&40, &'c')));
+
+    assert_eq!(map.nearest_by(&5, dist), Some((&10, &'a')));
+    assert_eq!(map.nearest_by(&14, dist), Some((&10, &'a')));
+    assert_eq!(map.nearest_by(&15, dist), Some((&10, &'a')));
+    assert_eq!(map.nearest_by(&16, dist), Some((&20, &'b')));
+    assert_eq!(map.nearest_by(&20, dist), Some((&20, &'b')));
+    assert_eq!(map.nearest_by(&31, dist), Some((&40, &'c')));
+    assert_eq!(map.nearest_by(&i32::MAX, dist), Some((&40, &'c')));
+
+    let empty: AvlTreeMap<i32, char> = AvlTreeMap::new();
+    assert_eq!(empty.nearest(&0), None);
+    assert_eq!(empty.nearest_by(&0, dist), None);
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, ()> = (0..N).map(|_| (rng.gen_range(0..10 * N), ())).collect();
+    for query in 0..10 * N {
+        let (key, _) = map.nearest_by(&query, dist).unwrap();
+        let best = map.keys().map(|key| dist(&query, key)).min().unwrap();
+        assert_eq!(dist(&query, key), best);
+    }
+}
+
+#[test]
+fn test_remove_all() {
+    let mut map: AvlTreeMap<i32, i32> = (0..10).map(|key| (key * 10, key)).collect();
+    assert_eq!(map.remove_all(&[-5, 0, 5, 10, 40, 41, 90, 100]), 4);
+    map.check_consistency();
+    assert!(map.keys().eq(&[20, 30, 50, 60, 70, 80]));
+    assert_eq!(map.remove_all(&[]), 0);
+    assert_eq!(map.remove_all(&[20, 20, 30]), 2);
+    assert!(map.keys().eq(&[50, 60, 70, 80]));
+
+    let mut empty: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    assert_eq!(empty.remove_all(&[1, 2]), 0);
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for batch_len in [1, 10, 100, N as usize] {
+        let mut map: AvlTreeMap<i32, ()> = (0..N).map(|_| (rng.gen_range(0..N), ())).collect();
+        let mut expected = map.clone();
+        let mut keys: Vec<i32> = (0..batch_len).map(|_| rng.gen_range(0..N)).collect();
+        keys.sort_unstable();
+        let expected_removed = keys
+            .iter()
+            .filter(|key| expected.remove(key).is_some())
+            .count();
+        assert_eq!(map.remove_all(&keys), expected_removed);
+        map.check_consistency();
+        assert_eq!(map, expected);
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "keys are not sorted")]
+fn test_remove_all_unsorted() {
+    let mut map: AvlTreeMap<i32, i32> = (0..10).map(|key| (key, key)).collect();
+    map.remove_all(&[3, 1]);
+}
+
+#[test]
+fn test_remove_range() {
+    use core::ops::RangeBounds;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let bounds = |a: i32, b: i32| {
+        let bound = |key, kind| match kind {
+            0 => Bound::Included(key),
+            1 => Bound::Excluded(key),
+            _ => Bound::Unbounded,
+        };
+        let (a, b) = (a.min(b), a.max(b));
+        let (start, end) = (bound(a, a % 3), bound(b, b % 3));
+        match (start, end) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => (start, Bound::Included(e)),
+            _ => (start, end),
+        }
+    };
+    for len in [0, 1, 2, 10, N as usize] {
+        let map: AvlTreeMap<i32, i32> = (0..len as i32).map(|key| (2 * key, key)).collect();
+        for _ in 0..20 {
+            let range = bounds(rng.gen_range(-2..2 * N + 2), rng.gen_range(-2..2 * N + 2));
+            let mut rest = map.clone();
+            let removed = rest.remove_range(range);
+            rest.check_consistency();
+            removed.check_consistency();
+            assert!(removed.iter().eq(map.range(range)));
+            assert_eq!(rest.len() + removed.len(), map.len());
+            assert!(rest.keys().all(|key| !range.contains(key)));
+        }
+    }
+}
+
+#[test]
+fn test_set_drain_range() {
+    let mut set: AvlTreeSet<String> = (0..100).map(|value| format!("{:02}", value)).collect();
+    let drained: Vec<String> = set
+        .drain_range::<str, _>((Bound::Included("40"), Bound::Excluded("60")))
+        .collect();
+    set.check_consistency();
+    let expected: Vec<String> = (40..60).map(|value| format!("{:02}", value)).collect();
+    assert_eq!(drained, expected);
+    assert_eq!(set.len(), 80);
+    assert!(set
+        .iter()
+        .map(|value| value.parse::<i32>().unwrap())
+        .eq((0..40).chain(60..100)));
+
+    // Dropping the iterator without consuming it still removes the values
+    let mut set: AvlTreeSet<i32> = (0..N).collect();
+    drop(set.drain_range(..10));
+    set.drain_range(N - 10..).next();
+    set.check_consistency();
+    assert!(set.iter().copied().eq(10..N - 10));
+    assert_eq!(set.drain_range(..).count(), (N - 20) as usize);
+    assert!(set.is_empty());
+}
+
+#[test]
+fn test_upsert() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let add = |amount: i32| move |sum: Option<i32>| sum.unwrap_or(0) + amount;
+    let mut map = AvlTreeMap::new();
+    for (key, amount) in [
+        ("a", 1),
+        ("b", 10),
+        ("a", 2),
+        ("c", 100),
+        ("b", 20),
+        ("a", 3),
+    ] {
+        map.upsert(key, add(amount));
+    }
+    assert!(map.iter().eq([(&"a", &6), (&"b", &30), (&"c", &100)]));
+    *map.upsert("d", add(5)) *= 2;
+    *map.upsert("a", add(1)) *= 2;
+    assert_eq!(map.get("d"), Some(&10));
+    assert_eq!(map.get("a"), Some(&14));
+    map.check_consistency();
+
+    // A panicking update removes the entry instead of leaving a moved-out value behind
+    let mut map: AvlTreeMap<i32, String> = (0..10).map(|key| (key, format!("{}", key))).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        map.upsert(5, |value| {
+            drop(value);
+            panic!("update failed")
+        });
+    }));
+    assert!(result.is_err());
+    map.check_consistency();
+    assert!(!map.contains_key(&5));
+    assert_eq!(map.len(), 9);
+}
+
+#[test]
+fn test_debug_assert_valid() {
+    // With the paranoid feature, every insert and remove below is checked as well
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = AvlTreeMap::new();
+    map.debug_assert_valid();
+    for _ in 0..10 * N {
+        let key = rng.gen_range(0..N);
+        if rng.gen_bool(0.6) {
+            map.insert(key, key);
+        } else {
+            map.remove(&key);
+        }
+        map.debug_assert_valid();
+    }
+    map.check_consistency();
+    let mut greater = map.split_off(&(N / 2));
+    map.debug_assert_valid();
+    greater.debug_assert_valid();
+    map.append(&mut greater);
+    map.debug_assert_valid();
+    map.clear();
+    map.debug_assert_valid();
+}
+
+#[test]
+fn test_subtree_len() {
+    // Sorted inserts of 1..=7 build a perfect tree:
+    //        4
+    //    2       6
+    //  1   3   5   7
+    let map: AvlTreeMap<i32, ()> = (1..=7).map(|key| (key, ())).collect();
+    assert_eq!(map.height(), 2);
+    assert_eq!(map.subtree_len(&4), Some(7));
+    assert_eq!(map.subtree_len(&2), Some(3));
+    assert_eq!(map.subtree_len(&6), Some(3));
+    for leaf in [1, 3, 5, 7] {
+        assert_eq!(map.subtree_len(&leaf), Some(1));
+    }
+    assert_eq!(map.subtree_len(&8), None);
+
+    // The root sub tree holds all entries, the depths sum up to the sub tree sizes
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, ()> = (0..N).map(|_| (rng.gen_range(0..N), ())).collect();
+    let (_, root_key, _) = map
+        .iter_with_depth()
+        .find(|(depth, _, _)| *depth == 0)
+        .unwrap();
+    assert_eq!(map.subtree_len(root_key), Some(map.len()));
+    let depth_sum: usize = map.iter_with_depth().map(|(depth, _, _)| depth + 1).sum();
+    let subtree_sum: usize = map.keys().map(|key| map.subtree_len(key).unwrap()).sum();
+    assert_eq!(depth_sum, subtree_sum);
+}
+
+#[test]
+fn test_set_from_range() {
+    for n in [0, 1, 2, 3, 100, 1023, 1024, N] {
+        let set = AvlTreeSet::from_range(0..n);
+        set.check_consistency();
+        assert!(set.iter().copied().eq(0..n));
+        assert_eq!(set, (0..n).collect());
+    }
+    assert!(AvlTreeSet::from_range(b'a'..b'd').iter().eq(b"abc"));
+    #[allow(clippy::reversed_empty_ranges)]
+    let empty = AvlTreeSet::from_range(5..0);
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_group_by() {
+    let map: AvlTreeMap<i32, i32> = [1, 3, 9, 10, 25, 27, 28, 40]
+        .iter()
+        .map(|key| (*key, -key))
+        .collect();
+    let groups: Vec<(i32, Vec<i32>)> = map
+        .group_by(|key| key / 10)
+        .map(|(day, group)| (day, group.iter().map(|(key, _)| **key).collect()))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (0, vec![1, 3, 9]),
+            (1, vec![10]),
+            (2, vec![25, 27, 28]),
+            (4, vec![40])
+        ]
+    );
+    assert!(map
+        .group_by(|key| key / 10)
+        .flat_map(|(_, group)| group)
+        .eq(map.iter()));
+
+    // Each entry forms a group with an injective projection, a single group with a constant one
+    assert_eq!(map.group_by(|key| *key).count(), map.len());
+    let (unit, all) = map.group_by(|_| ()).next().unwrap();
+    assert_eq!((unit, all.len()), ((), map.len()));
+
+    let empty: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    assert!(empty.group_by(|key| key / 10).next().is_none());
+}
+
+#[test]
+fn test_debug_tree() {
+    let map: AvlTreeMap<i32, char> = [(4, 'd'), (2, 'b'), (6, 'f'), (1, 'a'), (3, 'c')]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        format!("{:?}", map.debug_tree()),
+        "4: 'd' (height 2)\n    L: 2: 'b' (height 1)\n        L: 1: 'a' (height 0)\n        \
+         R: 3: 'c' (height 0)\n    R: 6: 'f' (height 0)\n"
+    );
+    // The flat map output is unchanged
+    assert_eq!(
+        format!("{:?}", map),
+        "{1: 'a', 2: 'b', 3: 'c', 4: 'd', 6: 'f'}"
+    );
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let map: AvlTreeMap<i32, ()> = (0..N).map(|_| (rng.gen(), ())).collect();
+    let output = format!("{:?}", map.debug_tree());
+    assert_eq!(output.lines().count(), map.len());
+    assert!(output.starts_with(&format!(
+        "{:?}",
+        map.iter_with_depth().find(|(d, _, _)| *d == 0).unwrap().1
+    )));
+    assert!(output.lines().all(|line| line.contains(" (height ")));
+
+    let empty: AvlTreeMap<i32, ()> = AvlTreeMap::new();
+    assert_eq!(format!("{:?}", empty.debug_tree()), "");
+}
+
+#[test]
+fn test_range_around() {
+    let map: AvlTreeMap<i32, ()> = (0..10).map(|key| (key * 10, ())).collect();
+    let keys = |range: super::map::Range<'_, i32, ()>| -> Vec<i32> {
+        range.map(|(key, _)| *key).collect()
+    };
+
+    assert_eq!(keys(map.range_around(&50, 2, 2)), [30, 40, 50, 60, 70]);
+    assert_eq!(keys(map.range_around(&45, 1, 1)), [40, 50, 60]);
+    assert_eq!(keys(map.range_around(&50, 0, 0)), [50]);
+
+    // Near the edges fewer elements exist
+    assert_eq!(keys(map.range_around(&10, 3, 1)), [0, 10, 20]);
+    assert_eq!(keys(map.range_around(&-5, 3, 1)), [0, 10]);
+    assert_eq!(keys(map.range_around(&80, 1, 5)), [70, 80, 90]);
+    assert_eq!(keys(map.range_around(&95, 2, 5)), [80, 90]);
+    assert_eq!(keys(map.range_around(&95, 0, 5)), []);
+    assert_eq!(
+        keys(map.range_around(&50, 100, 100)),
+        (0..10).map(|key| key * 10).collect::<Vec<_>>()
+    );
+
+    // The range iterates from both ends
+    assert_eq!(map.range_around(&50, 2, 2).next_back(), Some((&70, &())));
+
+    let empty: AvlTreeMap<i32, ()> = AvlTreeMap::new();
+    assert_eq!(empty.range_around(&0, 2, 2).next(), None);
+}
+
+#[test]
+fn test_extend_cloned() {
+    let source: AvlTreeMap<String, Vec<u8>> = (0..100)
+        .map(|i| (format!("{:03}", i), vec![i as u8]))
+        .collect();
+    let mut map: AvlTreeMap<String, Vec<u8>> = AvlTreeMap::new();
+    map.insert(String::from("050"), Vec::new());
+    map.insert(String::from("zzz"), Vec::new());
+    map.extend_cloned(&source);
+    map.extend_cloned(source.range_from::<str>(Bound::Included("090")));
+    map.check_consistency();
+    assert_eq!(map.len(), 101);
+    assert_eq!(map.get("050"), Some(&vec![50]));
+    assert_eq!(map.get("zzz"), Some(&Vec::new()));
+    assert!(source
+        .iter()
+        .all(|(key, value)| map.get(key) == Some(value)));
+
+    let words: Vec<String> = ["pear", "apple", "fig", "apple"].map(String::from).into();
+    let mut set: AvlTreeSet<String> = AvlTreeSet::new();
+    set.extend_cloned(&words);
+    set.check_consistency();
+    assert!(set.iter().eq(["apple", "fig", "pear"]));
+    assert_eq!(words.len(), 4);
+}
+
+#[test]
+fn test_take() {
+    let mut map = AvlTreeMap::with_capacity(N as usize);
+    map.extend((0..N).map(|key| (key, format!("{}", key))));
+    map.remove(&0);
+    let expected = map.clone();
+
+    let taken = map.take();
+    taken.check_consistency();
+    assert_eq!(taken, expected);
+    assert_eq!(taken.capacity(), N as usize);
+    assert!(map.is_empty());
+    assert_eq!(map.capacity(), 0);
+    map.check_consistency();
+
+    // The emptied map is usable again
+    map.insert(1, String::from("one"));
+    assert!(map.iter().eq([(&1, &String::from("one"))]));
+    assert!(AvlTreeMap::<i32, i32>::new().take().is_empty());
+}
+
+#[test]
+fn test_priority_queue() {
+    let mut map: AvlTreeMap<i32, i32> = AvlTreeMap::new();
+    assert_eq!(map.first_key_value_mut(), None);
+    assert_eq!(map.last_key_value_mut(), None);
+
+    map.extend((1..=5).map(|priority| (priority, 10)));
+    if let Some((key, value)) = map.first_key_value_mut() {
+        assert_eq!(*key, 1);
+        *value -= 1;
+    }
+    *map.last_key_value_mut().unwrap().1 += 5;
+    assert_eq!(map.first_key_value(), Some((&1, &9)));
+    assert_eq!(map.last_key_value(), Some((&5, &15)));
+
+    // Count down the minimum until it is used up, then pop it
+    while let Some((_, remaining)) = map.first_key_value_mut() {
+        *remaining -= 1;
+        if *remaining == 0 {
+            break;
+        }
+    }
+    assert_eq!(map.pop_first(), Some((1, 0)));
+    assert_eq!(map.first_key_value_mut(), Some((&2, &mut 10)));
+    map.check_consistency();
+}
+
+#[test]
+fn test_set_merge() {
+    let lhs: AvlTreeSet<i32> = [1, 2, 4, 6, 8].into_iter().collect();
+    let rhs: AvlTreeSet<i32> = [2, 3, 6, 9].into_iter().collect();
+    let merged: Vec<_> = lhs.merge(&rhs).collect();
+    assert_eq!(
+        merged,
+        [
+            (&1, Membership::OnlyLeft),
+            (&2, Membership::Both),
+            (&3, Membership::OnlyRight),
+            (&4, Membership::OnlyLeft),
+            (&6, Membership::Both),
+            (&8, Membership::OnlyLeft),
+            (&9, Membership::OnlyRight),
+        ]
+    );
+    let mut reversed: Vec<_> = lhs.merge(&rhs).rev().collect();
+    reversed.reverse();
+    assert_eq!(reversed, merged);
+    assert_eq!(
+        format!("{:?}", rhs.merge(&lhs).take(2).collect::<Vec<_>>()),
+        "[(1, OnlyRight), (2, Both)]"
+    );
+
+    // The classification subsumes the set operations
+    let mut rng = StdRng::seed_from_u64(0);
+    let lhs: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+    let rhs: AvlTreeSet<i32> = (0..N).map(|_| rng.gen_range(0..N)).collect();
+    let select = |membership: &[Membership]| -> Vec<i32> {
+        lhs.merge(&rhs)
+            .filter(|(_, m)| membership.contains(m))
+            .map(|(value, _)| *value)
+            .collect()
+    };
+    assert!(select(&[
+        Membership::OnlyLeft,
+        Membership::OnlyRight,
+        Membership::Both
+    ])
+    .iter()
+    .eq(lhs.union(&rhs)));
+    assert!(select(&[Membership::Both])
+        .iter()
+        .eq(lhs.intersection(&rhs)));
+    assert!(select(&[Membership::OnlyLeft])
+        .iter()
+        .eq(lhs.difference(&rhs)));
+    assert!(select(&[Membership::OnlyLeft, Membership::OnlyRight])
+        .iter()
+        .eq(lhs.symmetric_difference(&rhs)));
+}
+
+#[test]
+fn test_recompute_heights() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for len in [0, 1, 2, 3, 100, N as usize] {
+        let mut map = AvlTreeMap::new();
+        while map.len() < len {
+            let key: i32 = rng.gen();
+            map.insert(key, key);
+        }
+        let expected = map.clone();
+        let height = map.height();
+
+        map.corrupt_heights(7);
+        if len > 1 {
+            assert!(!map.is_consistent());
+        }
+        map.recompute_heights();
+        map.check_consistency();
+        assert_eq!(map.height(), height);
+        assert!(map.same_shape(&expected));
+
+        // Rebalancing works again after the repair
+        for key in 0..100 {
+            map.insert(key, key);
+            map.remove(&(key / 2));
+        }
+        map.check_consistency();
+    }
+}
+
+#[test]
+fn test_first_last_key_value_mut() {
+    let mut map: AvlTreeMap<String, i32> = AvlTreeMap::new();
+    assert_eq!(map.first_key_value_mut(), None);
+    assert_eq!(map.last_key_value_mut(), None);
+
+    map.extend(
+        ["b", "a", "d", "c"]
+            .iter()
+            .map(|key| (String::from(*key), 0)),
+    );
+    let (key, value) = map.first_key_value_mut().unwrap();
+    assert_eq!(key, "a");
+    *value = 100;
+    let (key, value) = map.last_key_value_mut().unwrap();
+    assert_eq!(key, "d");
+    *value -= 1;
+
+    // Only values have changed, the order of the keys is untouched
+    map.check_consistency();
+    assert!(map.keys().eq(["a", "b", "c", "d"]));
+    assert!(map.values().eq(&[100, 0, 0, -1]));
+    assert_eq!(map.first_key_value(), Some((&String::from("a"), &100)));
+}
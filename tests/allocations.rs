@@ -0,0 +1,153 @@
+//! Tests counting heap allocations.
+//!
+//! They install a counting global allocator, so they live in their own test binary
+//! and do not affect the allocations of the unit tests.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use avl::{AvlTreeMap, AvlTreeSet};
+
+const N: i32 = 1_000;
+
+/// Global allocator counting the allocations of the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by the current thread while executing `f`.
+fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(|count| count.get());
+    f();
+    ALLOCATIONS.with(|count| count.get()) - before
+}
+
+#[test]
+fn test_set_ops_into() {
+    // Filling an output buffer repeatedly should not allocate new nodes
+    let s1: AvlTreeSet<i32> = (0..N).filter(|x| x % 2 == 0).collect();
+    let s2: AvlTreeSet<i32> = (0..N).filter(|x| x % 3 == 0).collect();
+    let mut out = AvlTreeSet::new();
+    s1.union_into(&s2, &mut out);
+    let reused = count_allocations(|| {
+        for _ in 0..10 {
+            s1.union_into(&s2, &mut out);
+        }
+    });
+    let rebuilt = count_allocations(|| {
+        for _ in 0..10 {
+            out = s1.union(&s2).cloned().collect();
+        }
+    });
+    assert!(reused < rebuilt / 10);
+    assert!(out.is_consistent());
+    assert!(out.iter().eq(s1.union(&s2)));
+}
+
+#[test]
+fn test_clone_from() {
+    let source: AvlTreeMap<i32, String> = (0..N).map(|key| (key, format!("{}", key))).collect();
+    let mut target = source.clone();
+    let cloned_from = count_allocations(|| target.clone_from(&source));
+    let cloned = count_allocations(|| target = source.clone());
+    assert!(cloned_from < cloned / 10);
+    assert_eq!(target, source);
+}
+
+#[test]
+fn test_compact_into() {
+    for len in [0, 1, 2, 3, 100, 1023, 1024, 10 * N] {
+        let mut map: AvlTreeMap<_, _> = (0..len).map(|key| (key, key)).collect();
+        map.retain(|key, _| key % 4 != 0);
+        let mut compact = AvlTreeMap::new();
+        let allocations = count_allocations(|| compact = map.compact_into());
+        assert!(
+            allocations <= 1,
+            "Only the buffer of node pointers should be allocated"
+        );
+        assert!(compact.is_consistent());
+    }
+}
+
+#[test]
+fn test_node_reuse() {
+    // Alternating inserts and removes reuse the nodes of removed entries
+    let mut map = AvlTreeMap::with_capacity(16);
+    let allocations = count_allocations(|| {
+        for key in 0..N {
+            map.insert(key, key);
+            map.remove(&key);
+        }
+    });
+    assert_eq!(allocations, 0);
+
+    let mut map = AvlTreeMap::with_capacity(N as usize);
+    map.extend((0..N).map(|key| (key, key)));
+    let allocations = count_allocations(|| {
+        for key in 0..N {
+            map.remove(&key);
+            map.insert(key + N, key);
+        }
+    });
+    assert_eq!(allocations, 0);
+    assert!(map.is_consistent());
+
+    // Clear keeps the nodes for reuse
+    map.clear();
+    let allocations = count_allocations(|| {
+        for key in 0..N {
+            map.insert(key, key);
+        }
+    });
+    assert_eq!(allocations, 0);
+    assert!(map.is_consistent());
+}
+
+#[test]
+fn test_extend_batch() {
+    // A small batch is inserted without buffering, allocating the new nodes only
+    let mut map: AvlTreeMap<_, _> = (0..N).map(|key| (2 * key, key)).collect();
+    let allocations = count_allocations(|| map.extend((0..10).map(|key| (2 * key + 1, key))));
+    assert_eq!(allocations, 10);
+    assert!(map.is_consistent());
+    assert_eq!(map.len(), N as usize + 10);
+}
+
+#[test]
+fn test_set_replace() {
+    let mut set: AvlTreeSet<_> = (0..N).map(|key| (key, 0)).collect();
+    let allocations = count_allocations(|| {
+        for key in (0..N).step_by(7) {
+            assert!(set.replace((key, 0)).is_some());
+        }
+    });
+    assert_eq!(allocations, 0);
+    assert!(set.is_consistent());
+}
+
+#[test]
+fn test_take() {
+    let mut map = AvlTreeMap::with_capacity(N as usize);
+    map.extend((0..N).map(|key| (key, format!("{}", key))));
+    let mut taken = AvlTreeMap::new();
+    let allocations = count_allocations(|| taken = map.take());
+    assert_eq!(allocations, 0);
+    assert!(taken.is_consistent());
+    assert_eq!(taken.len(), N as usize);
+}